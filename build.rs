@@ -0,0 +1,6 @@
+fn main() {
+    // `include_dir` only tracks the embedded shaders on nightly, edits to them must still
+    // rebuild the crate.
+    println!("cargo:rerun-if-changed=wgsl");
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -0,0 +1,14 @@
+//! Renders a single frame without a window and writes it to `headless.png`.
+
+use voxel_renderer::graphics::{camera::Camera, Graphics};
+
+fn main() {
+    let mut graphics = Graphics::new_headless((640, 480));
+    graphics.render(&Camera::default(), 0.5);
+
+    graphics
+        .read_frame()
+        .unwrap()
+        .save_png("headless.png")
+        .unwrap();
+}
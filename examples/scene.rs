@@ -0,0 +1,40 @@
+//! Renders a procedural terrain defined in WGSL and writes it to `scene.png`.
+
+use voxel_renderer::graphics::{camera::Camera, scene::Scene, Graphics};
+
+const TERRAIN: &str = "
+const MATERIAL_GRASS = 1u;
+const MATERIAL_ROCK = 2u;
+
+fn scene_material(voxel_pos: vec3f, time: f32) -> u32 {
+    let height = sin(voxel_pos.x * 2.0) * 0.3 + cos(voxel_pos.z * 1.5) * 0.3 - 1.0;
+    if voxel_pos.y > height {
+        return MATERIAL_NONE;
+    }
+    if voxel_pos.y > height - 0.2 {
+        return MATERIAL_GRASS;
+    }
+    return MATERIAL_ROCK;
+}
+
+fn scene_material_color(material: u32) -> vec3f {
+    if material == MATERIAL_GRASS {
+        return vec3f(0.3, 0.7, 0.2);
+    }
+    return vec3f(0.5);
+}
+";
+
+fn main() {
+    let mut graphics = Graphics::new_headless((640, 480));
+    graphics
+        .set_scene(&Scene::source("terrain", TERRAIN))
+        .unwrap_or_else(|e| panic!("{e}"));
+    graphics.render(&Camera::default(), 0.0);
+
+    graphics
+        .read_frame()
+        .unwrap()
+        .save_png("scene.png")
+        .unwrap();
+}
@@ -0,0 +1,183 @@
+/// Seconds simulated by each update.
+pub const FIXED_DT: f32 = 1.0 / 60.0;
+/// Updates run for a single frame at most, a slow frame slows the simulation down instead of
+/// making the next one even slower.
+const MAX_UPDATES_PER_FRAME: u32 = 10;
+/// Scene time when the clock starts.
+const START_TIME: f32 = 0.5;
+/// Time scale multiplier of a single speed change.
+const TIME_SCALE_STEP: f32 = 2.0;
+const MIN_TIME_SCALE: f32 = 1.0 / 64.0;
+const MAX_TIME_SCALE: f32 = 64.0;
+
+/// Splits real time into fixed updates and keeps the scene time, which can be paused, scaled
+/// and stepped one update at a time.
+///
+/// Updates see the scene time move by whole updates, so the simulation is the same whatever the
+/// frame rate. Frames are rendered at [`Clock::render_time`] instead, between the last update and
+/// the next one, so animations stay smooth.
+pub struct Clock {
+    pub paused: bool,
+    /// Scene seconds per real second.
+    pub time_scale: f32,
+    time: f32,
+    /// Real seconds not simulated yet, less than an update.
+    accumulator: f32,
+    /// Updates to run while paused.
+    pending_steps: u32,
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            time_scale: 1.0,
+            time: START_TIME,
+            accumulator: 0.0,
+            pending_steps: 0,
+        }
+    }
+}
+
+impl Clock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the real seconds elapsed since the last frame, returning the number of updates to
+    /// run, each followed by [`Clock::tick`].
+    pub fn advance(&mut self, elapsed: f32) -> u32 {
+        self.accumulator += elapsed;
+        let updates = (self.accumulator / FIXED_DT).floor();
+        self.accumulator -= updates * FIXED_DT;
+        (updates as u32).min(MAX_UPDATES_PER_FRAME)
+    }
+
+    /// Moves the scene time forward by an update, unless paused without a pending step.
+    pub fn tick(&mut self) {
+        if self.paused {
+            if self.pending_steps == 0 {
+                return;
+            }
+            self.pending_steps -= 1;
+        }
+        self.time += FIXED_DT * self.time_scale;
+    }
+
+    /// Scene time of the last update.
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Fraction of an update elapsed since the last one, to interpolate between updates.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / FIXED_DT
+    }
+
+    /// Scene time of the frame being rendered, between two updates.
+    pub fn render_time(&self) -> f32 {
+        if self.paused {
+            self.time
+        } else {
+            self.time + self.alpha() * FIXED_DT * self.time_scale
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.pending_steps = 0;
+    }
+
+    /// Pauses the clock and moves the scene time forward by a single update.
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.pending_steps += 1;
+    }
+
+    /// Multiplies the time scale by [`TIME_SCALE_STEP`] per step, dividing it when negative.
+    pub fn change_time_scale(&mut self, steps: f32) {
+        self.time_scale =
+            (self.time_scale * TIME_SCALE_STEP.powf(steps)).clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn advance_splits_real_time_into_updates() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(FIXED_DT * 0.5), 0);
+        assert_close(clock.alpha(), 0.5);
+        assert_eq!(clock.advance(FIXED_DT * 2.0), 2);
+        assert_close(clock.alpha(), 0.5);
+    }
+
+    #[test]
+    fn advance_drops_updates_beyond_the_frame_limit() {
+        let mut clock = Clock::new();
+        assert_eq!(clock.advance(1.0), MAX_UPDATES_PER_FRAME);
+        assert!(clock.alpha() < 1.0);
+        assert_eq!(clock.advance(0.0), 0);
+    }
+
+    #[test]
+    fn ticks_move_the_scene_time_by_whole_updates() {
+        let mut clock = Clock::new();
+        clock.advance(FIXED_DT * 1.5);
+        clock.tick();
+        assert_close(clock.time(), START_TIME + FIXED_DT);
+        assert_close(clock.render_time(), START_TIME + FIXED_DT * 1.5);
+    }
+
+    #[test]
+    fn paused_clock_only_moves_by_steps() {
+        let mut clock = Clock::new();
+        clock.toggle_pause();
+        clock.advance(FIXED_DT * 1.5);
+        clock.tick();
+        assert_eq!(clock.time(), START_TIME);
+        assert_eq!(clock.render_time(), START_TIME);
+
+        clock.step();
+        clock.step();
+        for _ in 0..3 {
+            clock.tick();
+        }
+        assert_close(clock.time(), START_TIME + FIXED_DT * 2.0);
+
+        clock.toggle_pause();
+        clock.tick();
+        assert_close(clock.time(), START_TIME + FIXED_DT * 3.0);
+    }
+
+    #[test]
+    fn step_pauses_and_resuming_drops_pending_steps() {
+        let mut clock = Clock::new();
+        clock.step();
+        assert!(clock.paused);
+        clock.toggle_pause();
+        clock.toggle_pause();
+        clock.tick();
+        assert_eq!(clock.time(), START_TIME);
+    }
+
+    #[test]
+    fn time_scale_changes_by_steps_within_limits() {
+        let mut clock = Clock::new();
+        clock.change_time_scale(-1.0);
+        assert_eq!(clock.time_scale, 0.5);
+        clock.tick();
+        assert_close(clock.time(), START_TIME + FIXED_DT * 0.5);
+
+        clock.change_time_scale(100.0);
+        assert_eq!(clock.time_scale, MAX_TIME_SCALE);
+        clock.change_time_scale(-100.0);
+        assert_eq!(clock.time_scale, MIN_TIME_SCALE);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::maths::Vec2f;
+
+/// Stick values below this are ignored, resting sticks rarely report exactly zero.
+const DEADZONE: f32 = 0.15;
+
+/// Snapshot of the gamepad controls used by the camera.
+///
+/// Sticks are in `[-1, 1]` with y pointing up, triggers in `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GamepadState {
+    pub left_stick: Vec2f,
+    pub right_stick: Vec2f,
+    pub left_trigger: f32,
+    pub right_trigger: f32,
+    pub speed_up: bool,
+    pub speed_down: bool,
+}
+
+impl Default for GamepadState {
+    fn default() -> Self {
+        Self {
+            left_stick: Vec2f::new(0.0, 0.0),
+            right_stick: Vec2f::new(0.0, 0.0),
+            left_trigger: 0.0,
+            right_trigger: 0.0,
+            speed_up: false,
+            speed_down: false,
+        }
+    }
+}
+
+impl GamepadState {
+    /// Same state with the sticks zeroed inside the deadzone and rescaled outside of it.
+    pub fn with_deadzone(self) -> Self {
+        let filter = |stick: Vec2f| {
+            let length = (stick.x * stick.x + stick.y * stick.y).sqrt();
+            if length <= DEADZONE {
+                return Vec2f::new(0.0, 0.0);
+            }
+            stick * ((length.min(1.0) - DEADZONE) / (1.0 - DEADZONE) / length)
+        };
+
+        Self {
+            left_stick: filter(self.left_stick),
+            right_stick: filter(self.right_stick),
+            ..self
+        }
+    }
+}
+
+/// Provides the state of the gamepad, so synthetic input can replace a real device.
+pub trait GamepadSource {
+    /// Current state, `None` when no gamepad is connected.
+    fn poll(&mut self) -> Option<GamepadState>;
+}
+
+/// Always returns the same state.
+impl GamepadSource for GamepadState {
+    fn poll(&mut self) -> Option<GamepadState> {
+        Some(*self)
+    }
+}
+
+/// Gamepads of the system, available with the `gamepad` feature.
+pub fn system_gamepads() -> Option<Box<dyn GamepadSource>> {
+    #[cfg(feature = "gamepad")]
+    match gilrs::Gilrs::new() {
+        Ok(gilrs) => return Some(Box::new(GilrsGamepads { gilrs })),
+        Err(e) => eprintln!("Gamepad support disabled: {e}"),
+    }
+
+    None
+}
+
+/// Reads the first connected gamepad.
+#[cfg(feature = "gamepad")]
+pub struct GilrsGamepads {
+    gilrs: gilrs::Gilrs,
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadSource for GilrsGamepads {
+    fn poll(&mut self) -> Option<GamepadState> {
+        use gilrs::{Axis, Button};
+
+        // Events have to be consumed for the cached state to be updated.
+        while self.gilrs.next_event().is_some() {}
+
+        let (_, gamepad) = self.gilrs.gamepads().next()?;
+        let trigger = |button| gamepad.button_data(button).map_or(0.0, |data| data.value());
+
+        Some(GamepadState {
+            left_stick: Vec2f::new(
+                gamepad.value(Axis::LeftStickX),
+                gamepad.value(Axis::LeftStickY),
+            ),
+            right_stick: Vec2f::new(
+                gamepad.value(Axis::RightStickX),
+                gamepad.value(Axis::RightStickY),
+            ),
+            left_trigger: trigger(Button::LeftTrigger2),
+            right_trigger: trigger(Button::RightTrigger2),
+            speed_up: gamepad.is_pressed(Button::RightTrigger),
+            speed_down: gamepad.is_pressed(Button::LeftTrigger),
+        })
+    }
+}
@@ -0,0 +1,148 @@
+use std::{marker::PhantomData, mem::size_of, num::NonZeroU64, sync::Arc};
+
+use bytemuck::Pod;
+use wgpu::{util::DeviceExt, *};
+
+use super::ctx::GraphicsCtx;
+
+/// Uniform buffer holding a single `T`.
+pub struct UniformBuffer<T: Pod> {
+    pub(super) buffer: Arc<Buffer>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> UniformBuffer<T> {
+    pub fn new(ctx: &GraphicsCtx) -> Self {
+        Self::with_value(ctx, &T::zeroed())
+    }
+
+    pub fn with_value(ctx: &GraphicsCtx, value: &T) -> Self {
+        let buffer = ctx.device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some(std::any::type_name::<T>()),
+            contents: bytemuck::bytes_of(value),
+            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+        });
+
+        Self {
+            buffer: Arc::new(buffer),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Schedules an update, applied before the next submitted commands.
+    pub fn write(&self, ctx: &GraphicsCtx, value: &T) {
+        ctx.queue
+            .write_buffer(&self.buffer, 0, bytemuck::bytes_of(value));
+    }
+
+    pub fn layout_entry(binding: u32, visibility: ShaderStages) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(size_of::<T>() as u64),
+            },
+            count: None,
+        }
+    }
+
+    pub fn binding(&self, binding: u32) -> BindGroupEntry<'_> {
+        BindGroupEntry {
+            binding,
+            resource: self.buffer.as_entire_binding(),
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
+
+/// Storage buffer holding an array of `T`.
+pub struct StorageBuffer<T: Pod> {
+    pub(super) buffer: Arc<Buffer>,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> StorageBuffer<T> {
+    /// Zeroed buffer of `len` elements.
+    pub fn new(ctx: &GraphicsCtx, len: usize) -> Self {
+        Self::from_slice(ctx, &vec![T::zeroed(); len])
+    }
+
+    pub fn from_slice(ctx: &GraphicsCtx, data: &[T]) -> Self {
+        let buffer = ctx.device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some(std::any::type_name::<T>()),
+            contents: bytemuck::cast_slice(data),
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC | BufferUsages::STORAGE,
+        });
+
+        Self {
+            buffer: Arc::new(buffer),
+            len: data.len(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Schedules an update of the elements starting at `offset`, applied before the next
+    /// submitted commands.
+    pub fn write(&self, ctx: &GraphicsCtx, offset: usize, data: &[T]) {
+        assert!(
+            offset + data.len() <= self.len,
+            "Storage buffer write out of bounds"
+        );
+        ctx.queue.write_buffer(
+            &self.buffer,
+            (offset * size_of::<T>()) as u64,
+            bytemuck::cast_slice(data),
+        );
+    }
+
+    pub fn layout_entry(
+        binding: u32,
+        visibility: ShaderStages,
+        read_only: bool,
+    ) -> BindGroupLayoutEntry {
+        BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(size_of::<T>() as u64),
+            },
+            count: None,
+        }
+    }
+
+    pub fn binding(&self, binding: u32) -> BindGroupEntry<'_> {
+        BindGroupEntry {
+            binding,
+            resource: self.buffer.as_entire_binding(),
+        }
+    }
+
+    /// Copies the first `len` elements to the CPU, blocking until the GPU is done with them.
+    pub fn read(&self, ctx: &GraphicsCtx, len: usize) -> Vec<T> {
+        assert!(len <= self.len, "Storage buffer read out of bounds");
+        let data = ctx.read_buffer(&self.buffer, (len * size_of::<T>()) as u64);
+        data.chunks_exact(size_of::<T>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.buffer
+    }
+}
@@ -1,14 +1,72 @@
-use std::f32::NAN;
-
 use bytemuck::{Pod, Zeroable};
 use cgmath::InnerSpace;
 
-use crate::maths::{Vec2u, Vec3f};
+use crate::{
+    gamepad::GamepadState,
+    gpu_struct,
+    maths::{Vec2u, Vec3f},
+};
+
+/// Pitch is kept just short of vertical so the view never flips over.
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+/// Radians per second with the right stick fully tilted.
+const GAMEPAD_LOOK_SPEED: f32 = 2.5;
+/// Speed steps per second while a speed button is held.
+const GAMEPAD_SPEED_RATE: f32 = 8.0;
+/// Speed multiplier of a single step, a notch of the mouse wheel.
+const SPEED_STEP: f32 = 1.1;
+const MIN_SPEED: f32 = 0.05;
+const MAX_SPEED: f32 = 100.0;
+/// How fast the velocity reaches the speed of the held direction, per second.
+const ACCELERATION: f32 = 10.0;
+/// How fast the velocity fades once nothing is held, per second.
+const FRICTION: f32 = 6.0;
+const MIN_FOV: f32 = 10.0;
+const MAX_FOV: f32 = 120.0;
+/// Degrees of field of view per second while zooming.
+const ZOOM_RATE: f32 = 45.0;
+/// World units visible vertically when switching to an orthographic projection.
+const DEFAULT_ORTHOGRAPHIC_HEIGHT: f32 = 4.0;
+const MIN_ORTHOGRAPHIC_HEIGHT: f32 = 0.5;
+const MAX_ORTHOGRAPHIC_HEIGHT: f32 = 100.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    /// Rays spread from the camera position according to the field of view.
+    Perspective,
+    /// Parallel rays starting on a plane through the camera position, `height` world units
+    /// are visible vertically.
+    Orthographic { height: f32 },
+    /// Orthographic projection at the classic isometric angle, ignoring yaw and pitch.
+    Isometric { height: f32 },
+}
+
+impl Projection {
+    pub fn next(self) -> Self {
+        match self {
+            Projection::Perspective => Projection::Orthographic {
+                height: DEFAULT_ORTHOGRAPHIC_HEIGHT,
+            },
+            Projection::Orthographic { height } => Projection::Isometric { height },
+            Projection::Isometric { .. } => Projection::Perspective,
+        }
+    }
+}
 
+#[derive(Clone)]
 pub struct Camera {
+    pub projection: Projection,
+    /// Vertical field of view in degrees, used by the perspective projection.
+    pub fov: f32,
+    /// Distance to the plane in focus, for depth of field.
     pub focal_length: f32,
     pub position: Vec3f,
-    pub direction: Vec3f,
+    /// World units per second of the free-fly movement.
+    pub velocity: Vec3f,
+    /// Rotation around the up axis in radians, zero looking towards -Z.
+    pub yaw: f32,
+    /// Rotation above the horizon in radians.
+    pub pitch: f32,
     pub up: Vec3f,
     pub controller: CameraController,
 }
@@ -16,9 +74,13 @@ pub struct Camera {
 impl Default for Camera {
     fn default() -> Self {
         Self {
+            projection: Projection::Perspective,
+            fov: 90.0,
             focal_length: 1.0,
             position: Vec3f::new(0.0, 0.0, 0.0),
-            direction: Vec3f::new(0.0, 0.0, -1.0),
+            velocity: Vec3f::new(0.0, 0.0, 0.0),
+            yaw: 0.0,
+            pitch: 0.0,
             up: Vec3f::new(0.0, 1.0, 0.0),
             controller: CameraController {
                 forward: false,
@@ -27,41 +89,130 @@ impl Default for Camera {
                 right: false,
                 up: false,
                 down: false,
+                zoom_in: false,
+                zoom_out: false,
                 speed: 2.0,
+                sensitivity: 0.002,
+                invert_y: false,
+                gamepad: GamepadState::default(),
             },
         }
     }
 }
 
 impl Camera {
-    pub fn update_movement(&mut self, dt: f32) {
-        if self.controller.forward {
-            self.position += self.direction * self.controller.speed * dt;
+    pub fn direction(&self) -> Vec3f {
+        if let Projection::Isometric { .. } = self.projection {
+            // Looking down the diagonal of a cube.
+            return Vec3f::new(-1.0, -1.0, -1.0).normalize();
+        }
+
+        Vec3f::new(
+            -self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    /// Rotates the camera from a mouse movement in pixels.
+    pub fn look(&mut self, delta_x: f32, delta_y: f32) {
+        let sensitivity = self.controller.sensitivity;
+        self.rotate(-delta_x * sensitivity, -delta_y * sensitivity);
+    }
+
+    /// Adds to the yaw and pitch in radians, the pitch is inverted when `invert_y` is set.
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        let pitch = if self.controller.invert_y {
+            -pitch
+        } else {
+            pitch
+        };
+
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+
+    /// Widens the field of view by `degrees`, narrowing it when negative. Orthographic
+    /// projections scale their height by a matching ratio instead.
+    pub fn zoom(&mut self, degrees: f32) {
+        match &mut self.projection {
+            Projection::Perspective => self.fov = (self.fov + degrees).clamp(MIN_FOV, MAX_FOV),
+            Projection::Orthographic { height } | Projection::Isometric { height } => {
+                *height = (*height * (1.0 + degrees / self.fov))
+                    .clamp(MIN_ORTHOGRAPHIC_HEIGHT, MAX_ORTHOGRAPHIC_HEIGHT)
+            }
         }
-        if self.controller.backward {
-            self.position -= self.direction * self.controller.speed * dt;
+    }
+
+    /// Flies the camera, accelerating towards the held direction at the controller speed and
+    /// slowing down once released.
+    pub fn update_movement(&mut self, dt: f32) {
+        let controller = &self.controller;
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let pad = controller.gamepad.with_deadzone();
+
+        let direction = self.direction();
+        let right = direction.cross(self.up).normalize();
+        let mut wish = direction
+            * (axis(controller.forward, controller.backward) + pad.left_stick.y)
+            + right * (axis(controller.right, controller.left) + pad.left_stick.x)
+            + self.up
+                * (axis(controller.up, controller.down) + pad.right_trigger - pad.left_trigger);
+        // Diagonals aren't faster, partially tilted sticks still move slower.
+        if wish.magnitude2() > 1.0 {
+            wish = wish.normalize();
         }
-        if self.controller.left {
-            self.position += self.up.cross(self.direction) * self.controller.speed * dt;
+
+        // Exponential smoothing, independent of the frame rate.
+        let rate = if wish.magnitude2() > 0.0 {
+            ACCELERATION
+        } else {
+            FRICTION
+        };
+        let target = wish * controller.speed;
+        self.velocity += (target - self.velocity) * (1.0 - (-rate * dt).exp());
+        self.position += self.velocity * dt;
+
+        self.update_controls(dt);
+    }
+
+    /// Applies the controls shared by every movement mode: zoom, gamepad look and speed.
+    pub fn update_controls(&mut self, dt: f32) {
+        if self.controller.zoom_in {
+            self.zoom(-ZOOM_RATE * dt);
         }
-        if self.controller.right {
-            self.position -= self.up.cross(self.direction) * self.controller.speed * dt;
+        if self.controller.zoom_out {
+            self.zoom(ZOOM_RATE * dt);
         }
-        if self.controller.up {
-            self.position += self.up * self.controller.speed * dt;
+
+        let pad = self.controller.gamepad.with_deadzone();
+        self.rotate(
+            -pad.right_stick.x * GAMEPAD_LOOK_SPEED * dt,
+            pad.right_stick.y * GAMEPAD_LOOK_SPEED * dt,
+        );
+        if pad.speed_up {
+            self.controller.change_speed(GAMEPAD_SPEED_RATE * dt);
         }
-        if self.controller.down {
-            self.position -= self.up * self.controller.speed * dt;
+        if pad.speed_down {
+            self.controller.change_speed(-GAMEPAD_SPEED_RATE * dt);
         }
     }
 
     pub fn render_params(&self, dims: Vec2u) -> CameraRenderParams {
         let aspect_ratio = dims.x as f32 / dims.y as f32;
 
-        let viewport_height = 2.0;
+        // Perspective viewports are one unit in front of the camera, orthographic ones go
+        // through its position.
+        let (viewport_height, viewport_distance, orthographic) = match self.projection {
+            Projection::Perspective => (2.0 * (self.fov.to_radians() / 2.0).tan(), 1.0, false),
+            Projection::Orthographic { height } | Projection::Isometric { height } => {
+                (height, 0.0, true)
+            }
+        };
         let viewport_width = viewport_height * aspect_ratio;
 
-        let w = -self.direction.normalize();
+        let direction = self.direction();
+        let w = -direction;
         let u = self.up.cross(w).normalize();
         let v = w.cross(u);
 
@@ -72,17 +223,19 @@ impl Camera {
         let pixel_delta_v = viewport_v / dims.y as f32;
 
         let upper_left =
-            self.position - (self.focal_length * w) - viewport_u / 2.0 - viewport_v / 2.0;
+            self.position - viewport_distance * w - viewport_u / 2.0 - viewport_v / 2.0;
 
         CameraRenderParams {
             position: self.position.into(),
-            __padding0: NAN,
+            focal_length: self.focal_length,
             upper_left: upper_left.into(),
-            __padding1: NAN,
+            orthographic: orthographic as u32,
             pixel_delta_u: pixel_delta_u.into(),
-            __padding2: NAN,
+            __padding2: f32::NAN,
             pixel_delta_v: pixel_delta_v.into(),
-            __padding3: NAN,
+            __padding3: f32::NAN,
+            direction: direction.into(),
+            __padding4: f32::NAN,
         }
     }
 }
@@ -91,15 +244,28 @@ impl Camera {
 #[repr(C)]
 pub struct CameraRenderParams {
     position: [f32; 3],
-    __padding0: f32,
+    focal_length: f32,
     upper_left: [f32; 3],
-    __padding1: f32,
+    orthographic: u32,
     pixel_delta_u: [f32; 3],
     __padding2: f32,
     pixel_delta_v: [f32; 3],
     __padding3: f32,
+    direction: [f32; 3],
+    __padding4: f32,
 }
 
+gpu_struct!(CameraRenderParams as "Camera" {
+    position,
+    focal_length,
+    upper_left,
+    orthographic,
+    pixel_delta_u,
+    pixel_delta_v,
+    direction,
+});
+
+#[derive(Clone)]
 pub struct CameraController {
     pub forward: bool,
     pub backward: bool,
@@ -107,6 +273,125 @@ pub struct CameraController {
     pub right: bool,
     pub up: bool,
     pub down: bool,
+    pub zoom_in: bool,
+    pub zoom_out: bool,
 
+    /// World units per second, see [`CameraController::change_speed`].
     pub speed: f32,
+    /// Radians of rotation per pixel of mouse movement.
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    /// Gamepad input, applied along with the keys.
+    pub gamepad: GamepadState,
+}
+
+impl CameraController {
+    /// Multiplies the speed by [`SPEED_STEP`] per step, dividing it when negative, so it changes
+    /// as noticeably when slow as when fast. Clamped to stay positive.
+    pub fn change_speed(&mut self, steps: f32) {
+        self.speed = (self.speed * SPEED_STEP.powf(steps)).clamp(MIN_SPEED, MAX_SPEED);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maths::Vec2f;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn camera_with(gamepad: GamepadState) -> Camera {
+        let mut camera = Camera::default();
+        camera.controller.gamepad = gamepad;
+        camera
+    }
+
+    fn run(camera: &mut Camera, seconds: f32) {
+        for _ in 0..(seconds / DT) as u32 {
+            camera.update_movement(DT);
+        }
+    }
+
+    #[test]
+    fn resting_sticks_inside_the_deadzone_do_not_move() {
+        let mut camera = camera_with(GamepadState {
+            left_stick: Vec2f::new(0.1, -0.05),
+            right_stick: Vec2f::new(-0.1, 0.1),
+            ..Default::default()
+        });
+        run(&mut camera, 1.0);
+
+        assert_eq!(camera.position, Vec3f::new(0.0, 0.0, 0.0));
+        assert_eq!((camera.yaw, camera.pitch), (0.0, 0.0));
+    }
+
+    #[test]
+    fn left_stick_flies_towards_the_view_direction() {
+        let mut camera = camera_with(GamepadState {
+            left_stick: Vec2f::new(0.0, 1.0),
+            ..Default::default()
+        });
+        let direction = camera.direction();
+        run(&mut camera, 2.0);
+
+        let moved = camera.position.dot(direction);
+        assert!(moved > 0.0);
+        assert!((camera.position - direction * moved).magnitude() < 1e-4);
+        // Close to full speed once accelerated.
+        let speed = camera.controller.speed;
+        assert!((camera.velocity.magnitude() - speed).abs() < 0.01 * speed);
+    }
+
+    #[test]
+    fn half_tilted_stick_flies_slower() {
+        let mut full = camera_with(GamepadState {
+            left_stick: Vec2f::new(1.0, 0.0),
+            ..Default::default()
+        });
+        let mut half = camera_with(GamepadState {
+            left_stick: Vec2f::new(0.5, 0.0),
+            ..Default::default()
+        });
+        run(&mut full, 2.0);
+        run(&mut half, 2.0);
+
+        let ratio = half.velocity.magnitude() / full.velocity.magnitude();
+        assert!(ratio > 0.3 && ratio < 0.5, "{ratio}");
+    }
+
+    #[test]
+    fn triggers_fly_up_and_down() {
+        let mut camera = camera_with(GamepadState {
+            right_trigger: 1.0,
+            ..Default::default()
+        });
+        run(&mut camera, 1.0);
+        assert!(camera.position.y > 0.0);
+
+        camera.controller.gamepad = GamepadState {
+            left_trigger: 1.0,
+            ..Default::default()
+        };
+        let height = camera.position.y;
+        run(&mut camera, 1.0);
+        assert!(camera.position.y < height);
+    }
+
+    #[test]
+    fn speed_buttons_change_the_speed() {
+        let mut camera = camera_with(GamepadState {
+            speed_up: true,
+            ..Default::default()
+        });
+        let speed = camera.controller.speed;
+        run(&mut camera, 0.5);
+        assert!(camera.controller.speed > speed);
+
+        camera.controller.gamepad = GamepadState {
+            speed_down: true,
+            ..Default::default()
+        };
+        run(&mut camera, 60.0);
+        assert_eq!(camera.controller.speed, MIN_SPEED);
+    }
 }
@@ -0,0 +1,299 @@
+use std::{io, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use super::camera::Camera;
+use crate::maths::Vec3f;
+
+/// Seconds between recorded keyframes.
+const RECORD_INTERVAL: f32 = 0.25;
+
+/// Camera state at a point in time.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Seconds since the start of the path.
+    pub time: f32,
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub pitch: f32,
+    pub fov: f32,
+}
+
+impl Keyframe {
+    pub fn from_camera(camera: &Camera, time: f32) -> Self {
+        Self {
+            time,
+            position: camera.position.into(),
+            yaw: camera.yaw,
+            pitch: camera.pitch,
+            fov: camera.fov,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = Vec3f::from(self.position);
+        camera.yaw = self.yaw;
+        camera.pitch = self.pitch;
+        camera.fov = self.fov;
+    }
+}
+
+/// Keyframes sorted by time, saved as TOML.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CameraPath {
+    pub keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::read_to_string(path)?;
+        toml::from_str(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, file)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Camera state at `time`, interpolated with a Catmull-Rom spline going through every
+    /// keyframe. Clamped to the first and last keyframes.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keyframes = &self.keyframes;
+        let next = keyframes.partition_point(|keyframe| keyframe.time <= time);
+        if next == 0 || next == keyframes.len() {
+            return keyframes.get(next.saturating_sub(1)).copied();
+        }
+
+        let (k1, k2) = (keyframes[next - 1], keyframes[next]);
+        let k0 = keyframes[next.saturating_sub(2)];
+        let k3 = keyframes[(next + 1).min(keyframes.len() - 1)];
+
+        let u = (time - k1.time) / (k2.time - k1.time).max(f32::EPSILON);
+        let spline = |field: fn(&Keyframe) -> f32| {
+            catmull_rom(field(&k0), field(&k1), field(&k2), field(&k3), u)
+        };
+
+        Some(Keyframe {
+            time,
+            position: [
+                spline(|k| k.position[0]),
+                spline(|k| k.position[1]),
+                spline(|k| k.position[2]),
+            ],
+            yaw: spline(|k| k.yaw),
+            pitch: spline(|k| k.pitch),
+            fov: spline(|k| k.fov),
+        })
+    }
+}
+
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, u: f32) -> f32 {
+    0.5 * (2.0 * p1
+        + (p2 - p0) * u
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * u * u
+        + (3.0 * p1 - p0 - 3.0 * p2 + p3) * u * u * u)
+}
+
+/// Whether the camera path is being recorded or played back.
+#[derive(Default)]
+pub enum CameraPathPlayer {
+    #[default]
+    Idle,
+    Recording {
+        path: CameraPath,
+        time: f32,
+    },
+    Playing {
+        path: CameraPath,
+        time: f32,
+    },
+}
+
+impl CameraPathPlayer {
+    pub fn record() -> Self {
+        CameraPathPlayer::Recording {
+            path: CameraPath::default(),
+            time: 0.0,
+        }
+    }
+
+    pub fn play(path: CameraPath) -> Self {
+        CameraPathPlayer::Playing { path, time: 0.0 }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self, CameraPathPlayer::Playing { .. })
+    }
+
+    /// Records the camera or moves it along the path, playback stops at the end of the path.
+    pub fn update(&mut self, camera: &mut Camera, dt: f32) {
+        match self {
+            CameraPathPlayer::Idle => {}
+            CameraPathPlayer::Recording { path, time } => {
+                let due = path
+                    .keyframes
+                    .last()
+                    .is_none_or(|last| *time - last.time >= RECORD_INTERVAL);
+                if due {
+                    path.keyframes.push(Keyframe::from_camera(camera, *time));
+                }
+                *time += dt;
+            }
+            CameraPathPlayer::Playing { path, time } => {
+                *time += dt;
+                if let Some(keyframe) = path.sample(*time) {
+                    keyframe.apply(camera);
+                }
+                if *time >= path.duration() {
+                    *self = CameraPathPlayer::Idle;
+                }
+            }
+        }
+    }
+
+    /// Stops recording or playing, returning the recorded path.
+    pub fn stop(&mut self, camera: &Camera) -> Option<CameraPath> {
+        match std::mem::take(self) {
+            CameraPathPlayer::Recording { mut path, time } => {
+                path.keyframes.push(Keyframe::from_camera(camera, time));
+                Some(path)
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn keyframe(time: f32, x: f32) -> Keyframe {
+        Keyframe {
+            time,
+            position: [x, 1.0, -x],
+            yaw: x * 0.1,
+            pitch: -x * 0.05,
+            fov: 60.0 + x,
+        }
+    }
+
+    fn path() -> CameraPath {
+        CameraPath {
+            keyframes: vec![
+                keyframe(0.0, 0.0),
+                keyframe(1.0, 2.0),
+                keyframe(1.5, 3.0),
+                keyframe(3.0, -1.0),
+            ],
+        }
+    }
+
+    #[test]
+    fn samples_go_through_the_keyframes() {
+        let path = path();
+        for keyframe in &path.keyframes {
+            let sample = path.sample(keyframe.time).unwrap();
+            for (a, b) in sample.position.iter().zip(keyframe.position) {
+                assert!((a - b).abs() < 1e-5, "{sample:?} != {keyframe:?}");
+            }
+            assert!((sample.yaw - keyframe.yaw).abs() < 1e-5);
+            assert!((sample.pitch - keyframe.pitch).abs() < 1e-5);
+            assert!((sample.fov - keyframe.fov).abs() < 1e-4);
+        }
+
+        // Between keyframes, the spline starts towards the next one.
+        let sample = path.sample(0.1).unwrap();
+        assert!(sample.position[0] > 0.0 && sample.position[0] < 2.0);
+    }
+
+    #[test]
+    fn sampling_is_clamped_to_the_first_and_last_keyframes() {
+        let path = path();
+        assert_eq!(path.sample(-1.0), Some(path.keyframes[0]));
+        assert_eq!(path.sample(10.0), Some(path.keyframes[3]));
+    }
+
+    #[test]
+    fn empty_and_single_keyframe_paths() {
+        let empty = CameraPath::default();
+        assert_eq!(empty.sample(0.0), None);
+        assert_eq!(empty.duration(), 0.0);
+
+        let single = CameraPath {
+            keyframes: vec![keyframe(0.0, 1.0)],
+        };
+        assert_eq!(single.sample(-1.0), Some(single.keyframes[0]));
+        assert_eq!(single.sample(1.0), Some(single.keyframes[0]));
+        assert_eq!(single.duration(), 0.0);
+
+        // Playing stops right away instead of waiting for keyframes.
+        let mut camera = Camera::default();
+        let mut player = CameraPathPlayer::play(empty);
+        player.update(&mut camera, DT);
+        assert!(matches!(player, CameraPathPlayer::Idle));
+        let mut player = CameraPathPlayer::play(single);
+        player.update(&mut camera, DT);
+        assert!(matches!(player, CameraPathPlayer::Idle));
+        assert_eq!(camera.position, Vec3f::new(1.0, 1.0, -1.0));
+    }
+
+    #[test]
+    fn recording_keeps_a_keyframe_every_interval() {
+        let mut camera = Camera::default();
+        let mut player = CameraPathPlayer::record();
+        for _ in 0..60 {
+            camera.position.x += 0.1;
+            player.update(&mut camera, DT);
+        }
+        let path = player.stop(&camera).unwrap();
+        assert!(matches!(player, CameraPathPlayer::Idle));
+
+        let keyframes = &path.keyframes;
+        assert_eq!(keyframes[0].time, 0.0);
+        // Every interval, plus the camera when stopping.
+        assert_eq!(keyframes.len(), 5);
+        for pair in keyframes[..4].windows(2) {
+            let interval = pair[1].time - pair[0].time;
+            assert!((RECORD_INTERVAL..RECORD_INTERVAL + DT).contains(&interval));
+        }
+        assert_eq!(Vec3f::from(keyframes[4].position), camera.position);
+    }
+
+    #[test]
+    fn playback_stops_at_the_end_of_the_path() {
+        let path = path();
+        let mut camera = Camera::default();
+        let mut player = CameraPathPlayer::play(path.clone());
+
+        let mut time = 0.0;
+        while player.is_playing() {
+            player.update(&mut camera, DT);
+            time += DT;
+            assert!(time < path.duration() + 2.0 * DT);
+        }
+        assert!(time >= path.duration() - 1e-4);
+        assert_eq!(camera.position, Vec3f::from(path.keyframes[3].position));
+        assert_eq!(player.stop(&camera), None);
+    }
+
+    #[test]
+    fn paths_survive_a_save_and_load() {
+        let file = std::env::temp_dir().join(format!(
+            "voxel_renderer_camera_path_{}.toml",
+            std::process::id()
+        ));
+        let path = path();
+        path.save(&file).unwrap();
+        let loaded = CameraPath::load(&file);
+        std::fs::remove_file(&file).unwrap();
+
+        assert_eq!(loaded.unwrap(), path);
+    }
+}
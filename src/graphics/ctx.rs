@@ -1,30 +1,49 @@
 use wgpu::*;
 
-use super::Frame;
+use super::{pipeline_cache::PipelineCache, shader_files::ShaderFiles, Frame};
 
 pub struct GraphicsCtx<'w> {
     pub device: Device,
     pub queue: Queue,
-    pub surface: Surface<'w>,
+    pub target: RenderTarget<'w>,
     pub surface_texture_format: TextureFormat,
-    pub surface_capabilities: SurfaceCapabilities,
+    /// Where the passes load their shaders from.
+    pub shaders: ShaderFiles,
+    pub pipelines: PipelineCache,
 
     window_size: (u32, u32),
 }
 
+/// Where the final frame is rendered.
+pub enum RenderTarget<'w> {
+    Surface {
+        surface: Surface<'w>,
+        capabilities: SurfaceCapabilities,
+    },
+    /// Used when there is no window, the frame can be read back with [`GraphicsCtx::read_texture`].
+    Offscreen(Texture),
+}
+
 pub struct RenderCtx {
     pub view: TextureView,
     pub encoder: CommandEncoder,
 
-    surface_texture: SurfaceTexture,
+    surface_texture: Option<SurfaceTexture>,
 }
 
+/// Pixels read back from a texture, rows are tightly packed.
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    pub data: Vec<u8>,
+}
+
+const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
 impl<'w> GraphicsCtx<'w> {
     pub fn new(window_size: (u32, u32), target: impl Into<SurfaceTarget<'w>>) -> Self {
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: util::backend_bits_from_env().unwrap_or(Backends::all()),
-            ..Default::default()
-        });
+        let instance = create_instance();
         let surface = instance
             .create_surface(target)
             .unwrap_or_else(|e| panic!("Could not create graphics surface: {e}"));
@@ -34,30 +53,26 @@ impl<'w> GraphicsCtx<'w> {
             force_fallback_adapter: false,
         }))
         .unwrap();
-        let (device, queue) = pollster::block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::default(),
-            },
-            None,
-        ))
-        .unwrap_or_else(|e| panic!("Could not acquire graphics device: {e}"));
+        let (device, queue) = request_device(&adapter, Limits::default());
 
-        let surface_capabilities = surface.get_capabilities(&adapter);
-        let surface_texture_format = surface_capabilities
+        let capabilities = surface.get_capabilities(&adapter);
+        let surface_texture_format = capabilities
             .formats
             .iter()
             .copied()
             .find(|f| f.is_srgb())
-            .unwrap_or(surface_capabilities.formats[0]);
+            .unwrap_or(capabilities.formats[0]);
 
         let mut _self = Self {
             device,
             queue,
-            surface,
-            surface_capabilities,
+            target: RenderTarget::Surface {
+                surface,
+                capabilities,
+            },
             surface_texture_format,
+            shaders: ShaderFiles::from_env(),
+            pipelines: PipelineCache::new(),
             window_size,
         };
 
@@ -66,42 +81,86 @@ impl<'w> GraphicsCtx<'w> {
         _self
     }
 
+    /// Creates a context rendering into an offscreen texture, falling back to a software adapter
+    /// when no hardware one is available.
+    pub fn new_headless(size: (u32, u32)) -> Self {
+        let instance = create_instance();
+        let adapter = [false, true]
+            .into_iter()
+            .find_map(|force_fallback_adapter| {
+                pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    compatible_surface: None,
+                    force_fallback_adapter,
+                }))
+            })
+            .unwrap_or_else(|| panic!("Could not find a graphics adapter for headless rendering"));
+        let limits = Limits::downlevel_defaults().using_resolution(adapter.limits());
+        let (device, queue) = request_device(&adapter, limits);
+
+        let target = RenderTarget::Offscreen(create_offscreen_texture(&device, size));
+
+        Self {
+            device,
+            queue,
+            target,
+            surface_texture_format: OFFSCREEN_FORMAT,
+            shaders: ShaderFiles::from_env(),
+            pipelines: PipelineCache::new(),
+            window_size: size,
+        }
+    }
+
     pub fn resize(&mut self, window_size: (u32, u32)) {
         if window_size.0 > 0 && window_size.1 > 0 {
-            self.surface.configure(
-                &self.device,
-                &wgpu::SurfaceConfiguration {
-                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-                    format: self.surface_texture_format,
-                    width: window_size.0,
-                    height: window_size.1,
-                    present_mode: self.surface_capabilities.present_modes[0],
-                    alpha_mode: self.surface_capabilities.alpha_modes[0],
-                    view_formats: vec![],
-                    desired_maximum_frame_latency: 2,
-                },
-            );
+            match &mut self.target {
+                RenderTarget::Surface {
+                    surface,
+                    capabilities,
+                } => surface.configure(
+                    &self.device,
+                    &wgpu::SurfaceConfiguration {
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                        format: self.surface_texture_format,
+                        width: window_size.0,
+                        height: window_size.1,
+                        present_mode: capabilities.present_modes[0],
+                        alpha_mode: capabilities.alpha_modes[0],
+                        view_formats: vec![],
+                        desired_maximum_frame_latency: 2,
+                    },
+                ),
+                RenderTarget::Offscreen(texture) => {
+                    *texture = create_offscreen_texture(&self.device, window_size)
+                }
+            }
 
             self.window_size = window_size;
         }
     }
 
     pub fn next_frame<'a>(&'a mut self) -> Option<Frame<'a>> {
-        let surface_texture = self.surface.get_current_texture().map_err(|e| match e {
-            wgpu::SurfaceError::OutOfMemory => {
-                panic!("The system is out of memory for rendering!")
-            }
-            _ => format!("An error occured during surface texture acquisition: {e}"),
-        });
+        let (view, surface_texture) = match &self.target {
+            RenderTarget::Surface { surface, .. } => {
+                let surface_texture = surface.get_current_texture().map_err(|e| match e {
+                    wgpu::SurfaceError::OutOfMemory => {
+                        panic!("The system is out of memory for rendering!")
+                    }
+                    _ => format!("An error occured during surface texture acquisition: {e}"),
+                });
 
-        if surface_texture.is_err() {
-            return None;
-        }
-        let surface_texture = surface_texture.unwrap();
+                if surface_texture.is_err() {
+                    return None;
+                }
+                let surface_texture = surface_texture.unwrap();
 
-        let view = surface_texture
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+                let view = surface_texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+                (view, Some(surface_texture))
+            }
+            RenderTarget::Offscreen(texture) => (texture.create_view(&Default::default()), None),
+        };
         let encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -112,13 +171,144 @@ impl<'w> GraphicsCtx<'w> {
                 encoder,
                 view,
             },
+            size: self.window_size,
             ctx: self,
         })
     }
 
+    /// Creates a frame rendering into `view` instead of the render target.
+    pub fn frame_to<'a>(&'a self, view: TextureView, size: (u32, u32)) -> Frame<'a> {
+        let encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+
+        Frame {
+            render: RenderCtx {
+                surface_texture: None,
+                encoder,
+                view,
+            },
+            size,
+            ctx: self,
+        }
+    }
+
     pub fn window_size(&self) -> (u32, u32) {
         self.window_size
     }
+
+    pub fn offscreen_texture(&self) -> Option<&Texture> {
+        match &self.target {
+            RenderTarget::Offscreen(texture) => Some(texture),
+            RenderTarget::Surface { .. } => None,
+        }
+    }
+
+    /// Copies the first `size` bytes of a buffer to the CPU, blocking until the GPU is done with
+    /// it. The buffer needs the `COPY_SRC` usage.
+    pub fn read_buffer(&self, buffer: &Buffer, size: u64) -> Vec<u8> {
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, size);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = readback.slice(..);
+        slice.map_async(MapMode::Read, |result| {
+            result.unwrap_or_else(|e| panic!("Could not map readback buffer: {e}"))
+        });
+        self.device.poll(Maintain::Wait);
+
+        let data = slice.get_mapped_range().to_vec();
+        data
+    }
+
+    /// Copies a texture to the CPU, blocking until the GPU is done with it.
+    /// The texture needs the `COPY_SRC` usage.
+    pub fn read_texture(&self, texture: &Texture) -> Image {
+        self.read_texture_region(texture, (0, 0), (texture.width(), texture.height()))
+    }
+
+    /// Copies the `size` pixels of a texture starting at `origin` to the CPU, see
+    /// [`GraphicsCtx::read_texture`].
+    pub fn read_texture_region(
+        &self,
+        texture: &Texture,
+        origin: (u32, u32),
+        size: (u32, u32),
+    ) -> Image {
+        let (width, height) = size;
+        let format = texture.format();
+        let pixel_size = format
+            .block_copy_size(None)
+            .unwrap_or_else(|| panic!("Cannot read back textures of format {format:?}"));
+
+        let row_size = width * pixel_size;
+        let padded_row_size =
+            row_size.div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT) * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: padded_row_size as u64 * height as u64,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                origin: Origin3d {
+                    x: origin.0,
+                    y: origin.1,
+                    z: 0,
+                },
+                ..texture.as_image_copy()
+            },
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(MapMode::Read, |result| {
+            result.unwrap_or_else(|e| panic!("Could not map readback buffer: {e}"))
+        });
+        self.device.poll(Maintain::Wait);
+
+        let data = slice
+            .get_mapped_range()
+            .chunks(padded_row_size as usize)
+            .flat_map(|row| &row[..row_size as usize])
+            .copied()
+            .collect();
+
+        Image {
+            width,
+            height,
+            format,
+            data,
+        }
+    }
 }
 
 impl<'a> Frame<'a> {
@@ -126,6 +316,53 @@ impl<'a> Frame<'a> {
         self.ctx
             .queue
             .submit(std::iter::once(self.render.encoder.finish()));
-        self.render.surface_texture.present();
+        if let Some(surface_texture) = self.render.surface_texture {
+            surface_texture.present();
+        }
     }
 }
+
+fn create_instance() -> Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: util::backend_bits_from_env().unwrap_or(Backends::all()),
+        ..Default::default()
+    })
+}
+
+fn request_device(adapter: &Adapter, required_limits: Limits) -> (Device, Queue) {
+    pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            required_features: wgpu::Features::empty(),
+            required_limits,
+        },
+        None,
+    ))
+    .unwrap_or_else(|e| panic!("Could not acquire graphics device: {e}"))
+}
+
+fn create_offscreen_texture(device: &Device, size: (u32, u32)) -> Texture {
+    create_target_texture(device, size, OFFSCREEN_FORMAT)
+}
+
+/// Texture that can be rendered to in place of the surface and read back.
+pub fn create_target_texture(
+    device: &Device,
+    (width, height): (u32, u32),
+    format: TextureFormat,
+) -> Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("offscreen"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
@@ -0,0 +1,588 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+use wgpu::*;
+
+use super::{camera::Camera, ctx::GraphicsCtx, pass::voxel::DebugView, shader::ShaderError, Frame};
+
+/// Name of a texture or buffer shared between passes.
+pub type ResourceId = &'static str;
+
+/// A pass scheduled by the [`RenderGraph`].
+pub trait RenderPass {
+    fn name(&self) -> &'static str;
+
+    /// Resources read and written by the pass, used to allocate them and order the passes.
+    fn io(&self) -> PassIo;
+
+    /// Recreates the pipelines, called when the shaders are refreshed.
+    /// On error the previous pipelines must be kept.
+    fn refresh(&mut self, ctx: &GraphicsCtx) -> Result<(), ShaderError>;
+
+    /// Files the pipelines were compiled from, used to know when to refresh them.
+    fn shader_files(&self) -> &[PathBuf] {
+        &[]
+    }
+
+    /// Recreates the bind groups, called once the resources of the pass are (re)allocated.
+    fn bind(&mut self, ctx: &GraphicsCtx, resources: &Resources);
+
+    fn run(&mut self, frame: &mut Frame, resources: &Resources, inputs: &FrameInputs);
+
+    fn post_render(&mut self) {}
+}
+
+/// Per frame data available to every pass.
+pub struct FrameInputs<'a> {
+    pub camera: &'a Camera,
+    pub time: f32,
+    pub debug_view: DebugView,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureSize {
+    /// Follows the window size.
+    Window,
+    /// Window size multiplied by a factor.
+    Scaled(f32),
+    Fixed(u32, u32),
+}
+
+impl TextureSize {
+    fn resolve(self, (width, height): (u32, u32)) -> (u32, u32) {
+        match self {
+            TextureSize::Window => (width, height),
+            TextureSize::Scaled(factor) => (
+                ((width as f32 * factor) as u32).max(1),
+                ((height as f32 * factor) as u32).max(1),
+            ),
+            TextureSize::Fixed(width, height) => (width, height),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TextureDesc {
+    pub format: TextureFormat,
+    pub size: TextureSize,
+}
+
+impl TextureDesc {
+    pub fn window(format: TextureFormat) -> Self {
+        Self {
+            format,
+            size: TextureSize::Window,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BufferDesc {
+    pub size: u64,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ResourceDesc {
+    Texture(TextureDesc),
+    Buffer(BufferDesc),
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Usage {
+    Texture(TextureUsages),
+    Buffer(BufferUsages),
+}
+
+/// Declaration of the resources used by a pass.
+#[derive(Default)]
+pub struct PassIo {
+    reads: Vec<(ResourceId, Usage)>,
+    writes: Vec<(ResourceId, ResourceDesc, Usage)>,
+}
+
+impl PassIo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read_texture(mut self, id: ResourceId, usage: TextureUsages) -> Self {
+        self.reads.push((id, Usage::Texture(usage)));
+        self
+    }
+
+    pub fn write_texture(
+        mut self,
+        id: ResourceId,
+        desc: TextureDesc,
+        usage: TextureUsages,
+    ) -> Self {
+        self.writes
+            .push((id, ResourceDesc::Texture(desc), Usage::Texture(usage)));
+        self
+    }
+
+    pub fn read_buffer(mut self, id: ResourceId, usage: BufferUsages) -> Self {
+        self.reads.push((id, Usage::Buffer(usage)));
+        self
+    }
+
+    pub fn write_buffer(mut self, id: ResourceId, desc: BufferDesc, usage: BufferUsages) -> Self {
+        self.writes
+            .push((id, ResourceDesc::Buffer(desc), Usage::Buffer(usage)));
+        self
+    }
+
+    fn uses(&self) -> impl Iterator<Item = ResourceId> + '_ {
+        self.reads
+            .iter()
+            .map(|(id, _)| *id)
+            .chain(self.writes.iter().map(|(id, ..)| *id))
+    }
+}
+
+pub struct GraphTexture {
+    pub texture: Texture,
+    pub view: TextureView,
+}
+
+/// Resources allocated by the graph, looked up by passes when binding.
+#[derive(Default)]
+pub struct Resources {
+    textures: HashMap<ResourceId, GraphTexture>,
+    buffers: HashMap<ResourceId, Buffer>,
+}
+
+impl Resources {
+    pub fn texture(&self, id: ResourceId) -> &GraphTexture {
+        self.textures
+            .get(id)
+            .unwrap_or_else(|| panic!("Render graph has no texture named {id}"))
+    }
+
+    pub fn texture_view(&self, id: ResourceId) -> &TextureView {
+        &self.texture(id).view
+    }
+
+    pub fn buffer(&self, id: ResourceId) -> &Buffer {
+        self.buffers
+            .get(id)
+            .unwrap_or_else(|| panic!("Render graph has no buffer named {id}"))
+    }
+}
+
+struct ResourceNode {
+    desc: ResourceDesc,
+    usage: Usage,
+    allocated_size: Option<(u32, u32)>,
+}
+
+pub struct RenderGraph {
+    passes: Vec<Box<dyn RenderPass>>,
+    /// Passes whose last refresh failed, refreshed again on any shader change.
+    failed_refresh: HashSet<usize>,
+    order: Vec<usize>,
+    nodes: HashMap<ResourceId, ResourceNode>,
+    resources: Resources,
+    exports: Vec<(ResourceId, Usage)>,
+    size: (u32, u32),
+}
+
+impl Default for RenderGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            passes: Vec::new(),
+            failed_refresh: HashSet::new(),
+            order: Vec::new(),
+            nodes: HashMap::new(),
+            resources: Resources::default(),
+            exports: Vec::new(),
+            size: (0, 0),
+        }
+    }
+
+    pub fn with_pass(mut self, pass: impl RenderPass + 'static) -> Self {
+        self.add_pass(pass);
+        self
+    }
+
+    /// Adds a pass, [`RenderGraph::build`] must be called before the next frame.
+    pub fn add_pass(&mut self, pass: impl RenderPass + 'static) {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Adds usages to a resource for use outside of the passes, like copying it to the CPU.
+    pub fn with_export(mut self, id: ResourceId, usage: Usage) -> Self {
+        self.exports.push((id, usage));
+        self
+    }
+
+    pub fn pass_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.order.iter().map(|&i| self.passes[i].name())
+    }
+
+    pub fn resources(&self) -> &Resources {
+        &self.resources
+    }
+
+    /// Schedules the passes, allocates every declared resource and binds the passes to them.
+    pub fn build(&mut self, ctx: &GraphicsCtx) {
+        let ios: Vec<PassIo> = self.passes.iter().map(|pass| pass.io()).collect();
+
+        self.nodes = collect_nodes(&ios, &self.exports);
+        self.order = schedule(&ios, |i| self.passes[i].name());
+        self.resources = Resources::default();
+        self.size = ctx.window_size();
+        self.allocate(ctx);
+
+        for pass in &mut self.passes {
+            pass.bind(ctx, &self.resources);
+        }
+    }
+
+    /// Reallocates the resources depending on the window size and rebinds the passes using them.
+    pub fn resize(&mut self, ctx: &GraphicsCtx) {
+        self.resize_to(ctx, ctx.window_size());
+    }
+
+    /// Like [`RenderGraph::resize`] but with a size differing from the window, used for captures.
+    pub fn resize_to(&mut self, ctx: &GraphicsCtx, size: (u32, u32)) {
+        self.size = size;
+        let reallocated = self.allocate(ctx);
+        if reallocated.is_empty() {
+            return;
+        }
+
+        for pass in &mut self.passes {
+            if pass.io().uses().any(|id| reallocated.contains(id)) {
+                pass.bind(ctx, &self.resources);
+            }
+        }
+    }
+
+    /// Recreates the pipelines of every pass, passes failing to do so keep their previous ones.
+    pub fn refresh(&mut self, ctx: &GraphicsCtx) -> Vec<ShaderError> {
+        self.refresh_passes(ctx, |_, _| true)
+    }
+
+    /// Refreshes the passes compiled from one of the `changed` shader files.
+    pub fn refresh_changed(&mut self, ctx: &GraphicsCtx, changed: &[PathBuf]) -> Vec<ShaderError> {
+        self.refresh_passes(ctx, |pass, failed| {
+            failed
+                || pass
+                    .shader_files()
+                    .iter()
+                    .any(|file| changed.contains(file))
+        })
+    }
+
+    /// Files used by the pipelines of every pass.
+    pub fn shader_files(&self) -> impl Iterator<Item = &PathBuf> {
+        self.passes.iter().flat_map(|pass| pass.shader_files())
+    }
+
+    fn refresh_passes(
+        &mut self,
+        ctx: &GraphicsCtx,
+        filter: impl Fn(&dyn RenderPass, bool) -> bool,
+    ) -> Vec<ShaderError> {
+        let mut errors = Vec::new();
+        for (i, pass) in self.passes.iter_mut().enumerate() {
+            if !filter(pass.as_ref(), self.failed_refresh.contains(&i)) {
+                continue;
+            }
+
+            match pass.refresh(ctx) {
+                Ok(()) => {
+                    self.failed_refresh.remove(&i);
+                    pass.bind(ctx, &self.resources);
+                }
+                Err(e) => {
+                    self.failed_refresh.insert(i);
+                    errors.push(e);
+                }
+            }
+        }
+        errors
+    }
+
+    pub fn run(&mut self, frame: &mut Frame, inputs: &FrameInputs) {
+        for &i in &self.order {
+            self.passes[i].run(frame, &self.resources, inputs);
+        }
+    }
+
+    pub fn post_render(&mut self) {
+        for pass in &mut self.passes {
+            pass.post_render();
+        }
+    }
+
+    /// Allocates missing or outdated resources, returning the ones that changed.
+    fn allocate(&mut self, ctx: &GraphicsCtx) -> HashSet<ResourceId> {
+        let mut reallocated = HashSet::new();
+
+        for (&id, node) in &mut self.nodes {
+            match (node.desc, node.usage) {
+                (ResourceDesc::Texture(desc), Usage::Texture(usage)) => {
+                    let size = desc.size.resolve(self.size);
+                    if node.allocated_size == Some(size) {
+                        continue;
+                    }
+
+                    let texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some(id),
+                        size: Extent3d {
+                            width: size.0,
+                            height: size.1,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: desc.format,
+                        usage,
+                        view_formats: &[],
+                    });
+                    let view = texture.create_view(&Default::default());
+
+                    self.resources
+                        .textures
+                        .insert(id, GraphTexture { texture, view });
+                    node.allocated_size = Some(size);
+                }
+                (ResourceDesc::Buffer(desc), Usage::Buffer(usage)) => {
+                    if node.allocated_size.is_some() {
+                        continue;
+                    }
+
+                    let buffer = ctx.device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some(id),
+                        size: desc.size,
+                        usage,
+                        mapped_at_creation: false,
+                    });
+
+                    self.resources.buffers.insert(id, buffer);
+                    node.allocated_size = Some((0, 0));
+                }
+                _ => unreachable!("Resource usages are checked in collect_nodes"),
+            }
+            reallocated.insert(id);
+        }
+
+        reallocated
+    }
+}
+
+/// Merges the declarations of every pass, combining the usages of each resource.
+fn collect_nodes(
+    ios: &[PassIo],
+    exports: &[(ResourceId, Usage)],
+) -> HashMap<ResourceId, ResourceNode> {
+    let mut nodes: HashMap<ResourceId, ResourceNode> = HashMap::new();
+
+    for io in ios {
+        for &(id, desc, usage) in &io.writes {
+            match nodes.get_mut(id) {
+                Some(node) if node.desc != desc => {
+                    panic!("Render graph resource {id} is declared with conflicting descriptions")
+                }
+                Some(node) => node.usage = merge_usages(id, node.usage, usage),
+                None => {
+                    nodes.insert(
+                        id,
+                        ResourceNode {
+                            desc,
+                            usage,
+                            allocated_size: None,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    let reads = ios.iter().flat_map(|io| io.reads.iter()).chain(exports);
+    for &(id, usage) in reads {
+        let node = nodes
+            .get_mut(id)
+            .unwrap_or_else(|| panic!("Render graph resource {id} is read but never written"));
+        node.usage = merge_usages(id, node.usage, usage);
+    }
+
+    nodes
+}
+
+fn merge_usages(id: ResourceId, a: Usage, b: Usage) -> Usage {
+    match (a, b) {
+        (Usage::Texture(a), Usage::Texture(b)) => Usage::Texture(a | b),
+        (Usage::Buffer(a), Usage::Buffer(b)) => Usage::Buffer(a | b),
+        _ => panic!("Render graph resource {id} is used both as a texture and a buffer"),
+    }
+}
+
+/// Orders the passes so that every resource is written before being read.
+/// Passes writing the same resource keep their insertion order.
+fn schedule<'a>(ios: &[PassIo], name: impl Fn(usize) -> &'a str) -> Vec<usize> {
+    let mut dependencies: Vec<HashSet<usize>> = vec![HashSet::new(); ios.len()];
+    for (reader, io) in ios.iter().enumerate() {
+        for (writer, other) in ios.iter().enumerate() {
+            if writer == reader {
+                continue;
+            }
+            let writes = |id: ResourceId| other.writes.iter().any(|(w, ..)| *w == id);
+
+            let reads_output = io.reads.iter().any(|(id, _)| writes(id));
+            let writes_before = writer < reader && io.writes.iter().any(|(id, ..)| writes(id));
+            if reads_output || writes_before {
+                dependencies[reader].insert(writer);
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(ios.len());
+    let mut scheduled = vec![false; ios.len()];
+    while order.len() < ios.len() {
+        let ready = (0..ios.len()).find(|&i| {
+            !scheduled[i]
+                && dependencies[i]
+                    .iter()
+                    .all(|&dependency| scheduled[dependency])
+        });
+
+        match ready {
+            Some(i) => {
+                scheduled[i] = true;
+                order.push(i);
+            }
+            None => {
+                let cycle: Vec<&str> = (0..ios.len())
+                    .filter(|&i| !scheduled[i])
+                    .map(&name)
+                    .collect();
+                panic!("Render graph has a dependency cycle between passes {cycle:?}");
+            }
+        }
+    }
+
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXTURE: TextureDesc = TextureDesc {
+        format: TextureFormat::Rgba8Unorm,
+        size: TextureSize::Window,
+    };
+
+    fn writes(id: ResourceId) -> PassIo {
+        PassIo::new().write_texture(id, TEXTURE, TextureUsages::STORAGE_BINDING)
+    }
+
+    fn order(ios: &[PassIo]) -> Vec<usize> {
+        schedule(ios, |i| ["a", "b", "c", "d"][i])
+    }
+
+    #[test]
+    fn readers_run_after_writers_whatever_the_insertion_order() {
+        let ios = [
+            PassIo::new().read_texture("b", TextureUsages::TEXTURE_BINDING),
+            writes("b").read_texture("a", TextureUsages::TEXTURE_BINDING),
+            writes("a"),
+        ];
+        assert_eq!(order(&ios), [2, 1, 0]);
+    }
+
+    #[test]
+    fn writers_of_the_same_resource_keep_their_insertion_order() {
+        let ios = [
+            writes("a"),
+            PassIo::new().read_texture("a", TextureUsages::TEXTURE_BINDING),
+            writes("a"),
+            writes("b"),
+        ];
+        let order = order(&ios);
+        let position = |pass| order.iter().position(|&i| i == pass).unwrap();
+        assert!(position(0) < position(1));
+        assert!(position(0) < position(2));
+        assert_eq!(order.len(), 4);
+    }
+
+    #[test]
+    fn usages_of_reads_and_exports_are_merged() {
+        let ios = [
+            writes("a"),
+            PassIo::new().read_texture("a", TextureUsages::TEXTURE_BINDING),
+            writes("b").write_buffer("buffer", BufferDesc { size: 16 }, BufferUsages::STORAGE),
+            PassIo::new().read_buffer("buffer", BufferUsages::UNIFORM),
+        ];
+        let nodes = collect_nodes(
+            &ios,
+            &[
+                ("a", Usage::Texture(TextureUsages::COPY_SRC)),
+                ("b", Usage::Texture(TextureUsages::COPY_SRC)),
+            ],
+        );
+
+        assert_eq!(
+            nodes["a"].usage,
+            Usage::Texture(
+                TextureUsages::STORAGE_BINDING
+                    | TextureUsages::TEXTURE_BINDING
+                    | TextureUsages::COPY_SRC
+            )
+        );
+        assert_eq!(
+            nodes["b"].usage,
+            Usage::Texture(TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC)
+        );
+        assert_eq!(
+            nodes["buffer"].usage,
+            Usage::Buffer(BufferUsages::STORAGE | BufferUsages::UNIFORM)
+        );
+        assert_eq!(
+            nodes["buffer"].desc,
+            ResourceDesc::Buffer(BufferDesc { size: 16 })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "dependency cycle between passes [\"a\", \"b\"]")]
+    fn cycles_panic() {
+        let ios = [
+            writes("a").read_texture("b", TextureUsages::TEXTURE_BINDING),
+            writes("b").read_texture("a", TextureUsages::TEXTURE_BINDING),
+            writes("c"),
+        ];
+        order(&ios);
+    }
+
+    #[test]
+    #[should_panic(expected = "Render graph resource b is read but never written")]
+    fn reading_a_resource_nobody_writes_panics() {
+        let ios = [
+            writes("a"),
+            PassIo::new().read_texture("b", TextureUsages::TEXTURE_BINDING),
+        ];
+        collect_nodes(&ios, &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Render graph resource c is read but never written")]
+    fn exporting_a_resource_nobody_writes_panics() {
+        collect_nodes(
+            &[writes("a")],
+            &[("c", Usage::Texture(TextureUsages::COPY_SRC))],
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver},
+    time::{Duration, Instant},
+};
+
+use notify::{EventKind, RecursiveMode, Watcher};
+
+use super::shader_files::ShaderFiles;
+
+/// Editors often write files in several steps, changes are reported once a file is left alone
+/// for this long.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Watches a shader directory for changes, along with the files imported from outside of it.
+pub struct ShaderWatcher {
+    watcher: notify::RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+    dir: PathBuf,
+    /// Shader file paths of the watched files outside of `dir`, by canonical disk path.
+    outside_files: HashMap<PathBuf, PathBuf>,
+    outside_dirs: HashSet<PathBuf>,
+    pending: HashMap<PathBuf, Instant>,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: &Path) -> notify::Result<Self> {
+        let (sender, events) = channel();
+        let mut watcher = notify::recommended_watcher(sender)?;
+
+        let dir = std::fs::canonicalize(dir)?;
+        watcher.watch(&dir, RecursiveMode::Recursive)?;
+
+        Ok(Self {
+            watcher,
+            events,
+            dir,
+            outside_files: HashMap::new(),
+            outside_dirs: HashSet::new(),
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Also watches the files of `paths` read from disk outside of the watched directory, like
+    /// imports resolved from an absolute search root.
+    pub fn watch_files<'a>(
+        &mut self,
+        files: &ShaderFiles,
+        paths: impl IntoIterator<Item = &'a PathBuf>,
+    ) -> notify::Result<()> {
+        for path in paths {
+            let Some(disk_path) = files.disk_path(path) else {
+                continue;
+            };
+            let disk_path = std::fs::canonicalize(disk_path)?;
+            let Some(parent) = disk_path.parent() else {
+                continue;
+            };
+            if disk_path.starts_with(&self.dir) {
+                continue;
+            }
+
+            if self.outside_dirs.insert(parent.to_owned()) {
+                self.watcher.watch(parent, RecursiveMode::NonRecursive)?;
+            }
+            self.outside_files.insert(disk_path, path.clone());
+        }
+        Ok(())
+    }
+
+    /// Shader file paths changed since the last call, relative to the watched directory.
+    pub fn changed_files(&mut self) -> Vec<PathBuf> {
+        for event in self.events.try_iter() {
+            match event {
+                Ok(event)
+                    if matches!(
+                        event.kind,
+                        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+                    ) =>
+                {
+                    for path in event.paths {
+                        if let Some(path) = self.shader_path(&path) {
+                            self.pending.insert(path, Instant::now());
+                        }
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("Shader watcher error: {e}"),
+            }
+        }
+
+        let changed: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, time)| time.elapsed() >= DEBOUNCE)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in &changed {
+            self.pending.remove(path);
+        }
+        changed
+    }
+
+    fn shader_path(&self, disk_path: &Path) -> Option<PathBuf> {
+        if let Ok(path) = disk_path.strip_prefix(&self.dir) {
+            return Some(path.to_owned());
+        }
+        // Removed files can't be canonicalized anymore, their parent still can.
+        let canonical = std::fs::canonicalize(disk_path).ok().or_else(|| {
+            let parent = std::fs::canonicalize(disk_path.parent()?).ok()?;
+            Some(parent.join(disk_path.file_name()?))
+        })?;
+        self.outside_files.get(&canonical).cloned()
+    }
+}
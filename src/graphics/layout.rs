@@ -0,0 +1,220 @@
+use std::mem::size_of;
+
+use bytemuck::Pod;
+use naga::ScalarKind;
+
+/// A rust struct uploaded to the GPU, mirroring a WGSL struct.
+///
+/// Implement it with [`gpu_struct!`](crate::gpu_struct), the layout is then checked against the
+/// WGSL struct when the shader is loaded, see [`Shader::check_layout`](super::shader::Shader::check_layout).
+pub trait GpuStruct: Pod {
+    /// Name of the matching struct in WGSL.
+    const WGSL_NAME: &'static str;
+
+    /// Every field having a WGSL counterpart, padding excluded.
+    fn members() -> Vec<GpuMember>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GpuMember {
+    pub name: &'static str,
+    pub offset: usize,
+    pub size: usize,
+    /// Kind of the scalars making up the field, so `u32`, `i32` and `f32` can't be swapped.
+    pub scalar: ScalarKind,
+}
+
+/// Field type of a [`GpuStruct`], a scalar or an array of them standing for a WGSL vector,
+/// matrix or array.
+pub trait GpuField {
+    const SCALAR: ScalarKind;
+}
+
+impl GpuField for f32 {
+    const SCALAR: ScalarKind = ScalarKind::Float;
+}
+
+impl GpuField for u32 {
+    const SCALAR: ScalarKind = ScalarKind::Uint;
+}
+
+impl GpuField for i32 {
+    const SCALAR: ScalarKind = ScalarKind::Sint;
+}
+
+impl<T: GpuField, const N: usize> GpuField for [T; N] {
+    const SCALAR: ScalarKind = T::SCALAR;
+}
+
+/// Implements [`GpuStruct`] from the list of fields that exist in WGSL, in order.
+///
+/// ```ignore
+/// gpu_struct!(CameraRenderParams as "Camera" { position, upper_left });
+/// ```
+#[macro_export]
+macro_rules! gpu_struct {
+    ($ty:ty as $wgsl_name:literal { $($field:ident),* $(,)? }) => {
+        impl $crate::graphics::layout::GpuStruct for $ty {
+            const WGSL_NAME: &'static str = $wgsl_name;
+
+            fn members() -> Vec<$crate::graphics::layout::GpuMember> {
+                vec![$($crate::graphics::layout::GpuMember {
+                    name: stringify!($field),
+                    offset: std::mem::offset_of!($ty, $field),
+                    size: $crate::graphics::layout::field_size(|s: &$ty| &s.$field),
+                    scalar: $crate::graphics::layout::field_scalar(|s: &$ty| &s.$field),
+                }),*]
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+pub fn field_size<T, F>(_: impl Fn(&T) -> &F) -> usize {
+    size_of::<F>()
+}
+
+#[doc(hidden)]
+pub fn field_scalar<T, F: GpuField>(_: impl Fn(&T) -> &F) -> ScalarKind {
+    F::SCALAR
+}
+
+/// Kind of the scalars of a WGSL type, looking through arrays.
+fn wgsl_scalar(module: &naga::Module, ty: naga::Handle<naga::Type>) -> Option<ScalarKind> {
+    match module.types[ty].inner {
+        naga::TypeInner::Array { base, .. } => wgsl_scalar(module, base),
+        ref inner => inner.scalar_kind(),
+    }
+}
+
+/// Compares a rust struct to the WGSL struct of the same name in `module`.
+pub fn check_layout<T: GpuStruct>(module: &naga::Module) -> Result<(), String> {
+    let rust_name = std::any::type_name::<T>();
+    let mismatch = |reason: String| {
+        format!(
+            "{rust_name} does not match WGSL struct {}: {reason}",
+            T::WGSL_NAME
+        )
+    };
+
+    let (wgsl_members, wgsl_size) = module
+        .types
+        .iter()
+        .find_map(|(_, ty)| match &ty.inner {
+            naga::TypeInner::Struct { members, span }
+                if ty.name.as_deref() == Some(T::WGSL_NAME) =>
+            {
+                Some((members, *span))
+            }
+            _ => None,
+        })
+        .ok_or_else(|| mismatch("not found in shader".to_owned()))?;
+
+    let members = T::members();
+    if members.len() != wgsl_members.len() {
+        return Err(mismatch(format!(
+            "{} members in WGSL but {} in rust",
+            wgsl_members.len(),
+            members.len()
+        )));
+    }
+
+    for (member, wgsl_member) in members.iter().zip(wgsl_members) {
+        let wgsl_name = wgsl_member.name.as_deref().unwrap_or_default();
+        let wgsl_size = module.types[wgsl_member.ty].inner.size(module.to_ctx()) as usize;
+
+        if member.name != wgsl_name {
+            return Err(mismatch(format!(
+                "member {} in rust is {wgsl_name} in WGSL",
+                member.name
+            )));
+        }
+        if member.offset != wgsl_member.offset as usize {
+            return Err(mismatch(format!(
+                "{} is at offset {} in rust but {} in WGSL",
+                member.name, member.offset, wgsl_member.offset
+            )));
+        }
+        if member.size != wgsl_size {
+            return Err(mismatch(format!(
+                "{} is {} bytes in rust but {wgsl_size} in WGSL",
+                member.name, member.size
+            )));
+        }
+        if let Some(wgsl_scalar) = wgsl_scalar(module, wgsl_member.ty) {
+            if member.scalar != wgsl_scalar {
+                return Err(mismatch(format!(
+                    "{} is made of {:?} in rust but {wgsl_scalar:?} in WGSL",
+                    member.name, member.scalar
+                )));
+            }
+        }
+    }
+
+    if size_of::<T>() != wgsl_size as usize {
+        return Err(mismatch(format!(
+            "{} bytes in rust but {wgsl_size} in WGSL",
+            size_of::<T>()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bytemuck::{Pod, Zeroable};
+
+    use super::*;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Pod, Zeroable)]
+    struct Params {
+        origin: [i32; 3],
+        time: f32,
+        size: [u32; 3],
+        scale: f32,
+    }
+
+    gpu_struct!(Params as "Params" {
+        origin,
+        time,
+        size,
+        scale,
+    });
+
+    fn module(wgsl: &str) -> naga::Module {
+        naga::front::wgsl::parse_str(wgsl).unwrap()
+    }
+
+    #[test]
+    fn matching_layouts_pass() {
+        let module = module(
+            "struct Params { origin: vec3i, time: f32, size: vec3u, scale: f32 }
+            @group(0) @binding(0) var<uniform> params: Params;",
+        );
+        assert_eq!(check_layout::<Params>(&module), Ok(()));
+    }
+
+    #[test]
+    fn swapped_scalar_kinds_are_reported() {
+        let module = module(
+            "struct Params { origin: vec3u, time: f32, size: vec3i, scale: f32 }
+            @group(0) @binding(0) var<uniform> params: Params;",
+        );
+        let error = check_layout::<Params>(&module).unwrap_err();
+        assert!(
+            error.contains("origin is made of Sint in rust but Uint in WGSL"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn misplaced_members_are_reported() {
+        let module = module(
+            "struct Params { origin: vec3i, size: vec3u, time: f32, scale: f32 }
+            @group(0) @binding(0) var<uniform> params: Params;",
+        );
+        assert!(check_layout::<Params>(&module).is_err());
+    }
+}
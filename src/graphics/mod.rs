@@ -1,20 +1,47 @@
+use std::{io, path::PathBuf};
+
 use camera::Camera;
-use ctx::{GraphicsCtx, RenderCtx};
+use ctx::{GraphicsCtx, Image, RenderCtx};
+use graph::{FrameInputs, RenderGraph, Usage};
+use hot_reload::ShaderWatcher;
 use pass::{
     postproc::PostProcessingPass,
-    voxel::{VoxelPassParams, VoxelRenderingPass},
+    voxel::{DebugView, VoxelRenderingPass, VOXEL_COLOR, VOXEL_DEPTH},
 };
-use wgpu::SurfaceTarget;
+use probe::{ProbeError, VoxelGrid, VoxelProbe};
+use scene::Scene;
+use screenshot::{ScreenshotOptions, ScreenshotSource};
+use shader::ShaderError;
+use wgpu::{SurfaceTarget, TextureUsages};
+use wgsl::Defines;
+
+use crate::maths::{Vec3i, Vec3u};
 
+pub mod buffer;
 pub mod camera;
+pub mod camera_path;
 pub mod ctx;
+pub mod graph;
+pub mod hot_reload;
+pub mod layout;
+pub mod orbit;
 pub mod pass;
+pub mod pipeline;
+pub mod pipeline_cache;
+pub mod probe;
+pub mod scene;
+pub mod screenshot;
+pub mod shader;
+pub mod shader_files;
+pub mod walk;
 pub mod wgsl;
 
 pub struct Graphics<'w> {
     pub ctx: GraphicsCtx<'w>,
-    voxel_pass: VoxelRenderingPass,
-    postproc_pass: PostProcessingPass,
+    pub debug_view: DebugView,
+    graph: RenderGraph,
+    probe: VoxelProbe,
+    shader_watcher: Option<ShaderWatcher>,
 }
 
 impl<'w> Graphics<'w> {
@@ -25,53 +52,260 @@ impl<'w> Graphics<'w> {
         Self::new_from_ctx(ctx)
     }
 
+    /// Renders without a window, see [`Graphics::read_frame`] to get the rendered pixels.
+    pub fn new_headless(size: impl Into<(u32, u32)>) -> Self {
+        Self::new_from_ctx(GraphicsCtx::new_headless(size.into()))
+    }
+
     fn new_from_ctx(ctx: GraphicsCtx<'w>) -> Self {
-        let (postproc_pass, post_proc_input) = PostProcessingPass::new(&ctx, ctx.window_size());
-        let voxel_pass = VoxelRenderingPass::new(&ctx, post_proc_input);
+        let scene = Scene::default();
+        let graph = create_graph(&ctx, &scene).unwrap_or_else(|e| panic!("{e}"));
+        let probe = VoxelProbe::new(&ctx, &scene).unwrap_or_else(|e| panic!("{e}"));
 
         Self {
-            voxel_pass,
-            postproc_pass,
+            graph,
+            probe,
             ctx,
+            debug_view: DebugView::default(),
+            shader_watcher: None,
         }
     }
 
+    /// Traces `scene` instead of the current one, which is kept if the new one fails to compile.
+    pub fn set_scene(&mut self, scene: &Scene) -> Result<(), ShaderError> {
+        let shaders = scene.register(self.ctx.shaders.clone());
+        // Pipelines load their shaders from the context, the previous files are restored when
+        // they fail so a broken source doesn't replace the current scene on the next reload.
+        let previous = std::mem::replace(&mut self.ctx.shaders, shaders);
+        let built = create_graph(&self.ctx, scene)
+            .and_then(|graph| Ok((graph, VoxelProbe::new(&self.ctx, scene)?)));
+        let (graph, probe) = match built {
+            Ok(built) => built,
+            Err(e) => {
+                self.ctx.shaders = previous;
+                return Err(e);
+            }
+        };
+
+        self.graph = graph;
+        self.probe = probe;
+        self.watch_shader_files();
+        Ok(())
+    }
+
+    /// Reloads the shaders, printing the errors of those failing to compile.
     pub fn refresh(&mut self) {
-        let ctx = &self.ctx;
-        let (postproc_pass, post_proc_input) = PostProcessingPass::new(&ctx, ctx.window_size());
-        let voxel_pass = VoxelRenderingPass::new(&ctx, post_proc_input);
+        for e in self.graph.refresh(&self.ctx) {
+            eprintln!("Shader refresh failed, keeping the previous pipeline: {e}");
+        }
+        if let Err(e) = self.probe.refresh(&self.ctx) {
+            eprintln!("Shader refresh failed, keeping the previous pipeline: {e}");
+        }
+        self.watch_shader_files();
+    }
+
+    /// Refreshes passes automatically when one of their shader files changes on disk, in the
+    /// shader override directory or imported from outside of it. Does nothing when shaders are
+    /// only embedded.
+    pub fn watch_shaders(&mut self) -> notify::Result<()> {
+        self.shader_watcher = match self.ctx.shaders.override_dir() {
+            Some(dir) => Some(ShaderWatcher::new(dir)?),
+            None => None,
+        };
+        self.watch_shader_files();
+        Ok(())
+    }
 
-        self.voxel_pass = voxel_pass;
-        self.postproc_pass = postproc_pass;
+    /// Watches the files used by the current pipelines, imports can change on every reload.
+    fn watch_shader_files(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        let files = self.graph.shader_files().chain(self.probe.shader_files());
+        if let Err(e) = watcher.watch_files(&self.ctx.shaders, files) {
+            eprintln!("Could not watch shader files: {e}");
+        }
+    }
+
+    /// Loads shaders from `dir` before falling back to the embedded ones, and reloads them.
+    pub fn set_shader_override_dir(&mut self, dir: Option<PathBuf>) -> notify::Result<()> {
+        let watching = self.shader_watcher.is_some();
+        self.ctx.shaders = self.ctx.shaders.clone().with_override_dir(dir);
+        self.refresh();
+
+        if watching {
+            self.watch_shaders()?;
+        }
+        Ok(())
+    }
+
+    fn reload_changed_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        let changed = watcher.changed_files();
+        if changed.is_empty() {
+            return;
+        }
+
+        for e in self.graph.refresh_changed(&self.ctx, &changed) {
+            eprintln!("Shader reload failed, keeping the previous pipeline: {e}");
+        }
+        let probe_changed = self
+            .probe
+            .shader_files()
+            .iter()
+            .any(|file| changed.contains(file));
+        if probe_changed {
+            if let Err(e) = self.probe.refresh(&self.ctx) {
+                eprintln!("Shader reload failed, keeping the previous pipeline: {e}");
+            }
+        }
+        self.watch_shader_files();
     }
 
     pub fn resize(&mut self, window_size: impl Into<(u32, u32)>) {
         self.ctx.resize(window_size.into());
+        self.graph.resize(&self.ctx);
     }
 
     pub fn render(&mut self, camera: &Camera, time: f32) {
-        if let Some(mut frame) = self.ctx.next_frame() {
-            let (width, height) = frame.ctx.window_size();
+        self.reload_changed_shaders();
 
-            self.voxel_pass.run(
+        if let Some(mut frame) = self.ctx.next_frame() {
+            self.graph.run(
                 &mut frame,
-                camera,
-                VoxelPassParams {
+                &FrameInputs {
+                    camera,
                     time,
-                    width,
-                    height,
+                    debug_view: self.debug_view,
                 },
             );
-            self.postproc_pass.run(&mut frame);
 
             frame.present();
 
-            self.voxel_pass.post_render();
+            self.graph.post_render();
+        }
+    }
+
+    /// Renders a frame offscreen and reads it back, possibly at a higher resolution than the window.
+    /// Fails when the window is minimized to a zero size.
+    pub fn capture(
+        &mut self,
+        camera: &Camera,
+        time: f32,
+        options: ScreenshotOptions,
+    ) -> io::Result<Image> {
+        let (width, height) = self.ctx.window_size();
+        if width == 0 || height == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Cannot capture a zero sized window",
+            ));
         }
+        let max_size = self.ctx.device.limits().max_texture_dimension_2d;
+        let scale = options
+            .scale
+            .clamp(1, (max_size / width.max(height)).max(1));
+        let size = (width * scale, height * scale);
+
+        let target =
+            ctx::create_target_texture(&self.ctx.device, size, self.ctx.surface_texture_format);
+
+        self.graph.resize_to(&self.ctx, size);
+        let mut frame = self
+            .ctx
+            .frame_to(target.create_view(&Default::default()), size);
+        self.graph.run(
+            &mut frame,
+            &FrameInputs {
+                camera,
+                time,
+                debug_view: self.debug_view,
+            },
+        );
+        frame.present();
+        self.graph.post_render();
+
+        let image = match options.source {
+            ScreenshotSource::Frame => self.ctx.read_texture(&target),
+            ScreenshotSource::VoxelOutput => self
+                .ctx
+                .read_texture(&self.graph.resources().texture(VOXEL_COLOR).texture),
+        };
+
+        self.graph.resize(&self.ctx);
+        Ok(image)
     }
+
+    /// Captures a frame and saves it as a timestamped png in `dir`, returning its path.
+    pub fn screenshot(
+        &mut self,
+        camera: &Camera,
+        time: f32,
+        options: ScreenshotOptions,
+        dir: impl Into<PathBuf>,
+    ) -> io::Result<PathBuf> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+
+        let path = screenshot::screenshot_path(&dir);
+        self.capture(camera, time, options)?.save_png(&path)?;
+        Ok(path)
+    }
+
+    /// Materials of the `size` voxels of the scene starting at `origin`, see [`VoxelProbe`].
+    pub fn sample_voxels(
+        &self,
+        origin: Vec3i,
+        size: Vec3u,
+        time: f32,
+    ) -> Result<VoxelGrid, ProbeError> {
+        self.probe.sample(&self.ctx, origin, size, time)
+    }
+
+    /// Materials of at least the `size` voxels starting at `origin`, reusing the last probed
+    /// voxels while they cover them and are recent enough, see [`VoxelProbe::sample_around`].
+    pub fn voxels_around(
+        &mut self,
+        origin: Vec3i,
+        size: Vec3u,
+        time: f32,
+    ) -> Result<&VoxelGrid, ProbeError> {
+        self.probe.sample_around(&self.ctx, origin, size, time)
+    }
+
+    /// Distance from the camera to the voxel at the center of the last rendered frame, `None`
+    /// when the center ray escaped or nothing was rendered yet, the texture starting out zeroed.
+    pub fn center_depth(&self) -> Option<f32> {
+        let texture = &self.graph.resources().texture(VOXEL_DEPTH).texture;
+        let center = (texture.width() / 2, texture.height() / 2);
+        let depth = self.ctx.read_texture_region(texture, center, (1, 1));
+        let distance = f32::from_ne_bytes(depth.data[..4].try_into().unwrap());
+        (distance > 0.0).then_some(distance)
+    }
+
+    /// Reads back the last rendered frame, only available for headless graphics.
+    pub fn read_frame(&self) -> Option<Image> {
+        self.ctx
+            .offscreen_texture()
+            .map(|texture| self.ctx.read_texture(texture))
+    }
+}
+
+fn create_graph(ctx: &GraphicsCtx, scene: &Scene) -> Result<RenderGraph, ShaderError> {
+    let mut graph = RenderGraph::new()
+        .with_pass(VoxelRenderingPass::with_scene(ctx, scene, Defines::new())?)
+        .with_pass(PostProcessingPass::new(ctx)?)
+        .with_export(VOXEL_COLOR, Usage::Texture(TextureUsages::COPY_SRC))
+        .with_export(VOXEL_DEPTH, Usage::Texture(TextureUsages::COPY_SRC));
+    graph.build(ctx);
+    Ok(graph)
 }
 
 pub struct Frame<'a> {
     pub ctx: &'a GraphicsCtx<'a>,
     pub render: RenderCtx,
+    /// Size of the rendered image, usually the window size.
+    pub size: (u32, u32),
 }
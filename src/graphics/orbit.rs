@@ -0,0 +1,73 @@
+use cgmath::InnerSpace;
+
+use super::camera::Camera;
+use crate::maths::Vec3f;
+
+/// Distance to the focus point when there is no voxel to focus on.
+pub const DEFAULT_ORBIT_DISTANCE: f32 = 3.0;
+const MIN_DISTANCE: f32 = 0.1;
+const MAX_DISTANCE: f32 = 100.0;
+/// Distance multiplier of a single mouse wheel notch.
+const DOLLY_STEP: f32 = 1.1;
+/// Fraction of the distance to the focus point panned per pixel of mouse movement, so the
+/// focus point follows the cursor at any distance.
+const PAN_RATE: f32 = 0.0015;
+
+/// Turntable camera rotating around a focus point, the camera keeps its yaw and pitch and is
+/// placed `distance` units behind the focus point.
+pub struct Orbit {
+    pub focus: Vec3f,
+    pub distance: f32,
+}
+
+impl Orbit {
+    /// Orbits around `focus`, from the current distance of the camera.
+    pub fn new(camera: &Camera, focus: Vec3f) -> Self {
+        Self {
+            focus,
+            distance: (focus - camera.position)
+                .magnitude()
+                .clamp(MIN_DISTANCE, MAX_DISTANCE),
+        }
+    }
+
+    /// Orbits around the point `distance` units in front of the camera, or the default distance
+    /// when there is nothing in front of it.
+    pub fn in_front(camera: &Camera, distance: Option<f32>) -> Self {
+        let distance = distance.unwrap_or(DEFAULT_ORBIT_DISTANCE);
+        Self::new(camera, camera.position + camera.direction() * distance)
+    }
+
+    /// Rotates around the focus point from a mouse movement in pixels.
+    pub fn rotate(&self, camera: &mut Camera, delta_x: f32, delta_y: f32) {
+        camera.look(delta_x, delta_y);
+        self.apply(camera);
+    }
+
+    /// Moves the focus point with the view from a mouse movement in pixels, dragging the scene
+    /// along with the cursor.
+    pub fn pan(&mut self, camera: &mut Camera, delta_x: f32, delta_y: f32) {
+        let direction = camera.direction();
+        let right = direction.cross(camera.up).normalize();
+        let up = right.cross(direction);
+
+        self.focus += (up * delta_y - right * delta_x) * self.distance * PAN_RATE;
+        self.apply(camera);
+    }
+
+    /// Moves towards the focus point by [`DOLLY_STEP`] per step, away from it when negative.
+    pub fn dolly(&mut self, camera: &mut Camera, steps: f32) {
+        self.distance = (self.distance / DOLLY_STEP.powf(steps)).clamp(MIN_DISTANCE, MAX_DISTANCE);
+        self.apply(camera);
+    }
+
+    /// Applies the zoom and gamepad controls, then keeps the camera on the orbit.
+    pub fn update(&self, camera: &mut Camera, dt: f32) {
+        camera.update_controls(dt);
+        self.apply(camera);
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.position = self.focus - camera.direction() * self.distance;
+    }
+}
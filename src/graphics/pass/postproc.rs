@@ -1,92 +1,30 @@
-use crate::graphics::{ctx::GraphicsCtx, wgsl::load_wgsl_with_preprocessor, Frame};
+use std::path::PathBuf;
+
+use crate::graphics::{
+    ctx::GraphicsCtx,
+    graph::{FrameInputs, PassIo, RenderPass, Resources},
+    pipeline::{Binding, PassPipeline, PipelineBuilder},
+    shader::ShaderError,
+    wgsl::Defines,
+    Frame,
+};
 use wgpu::*;
 
+use super::voxel::{VOXEL_COLOR, VOXEL_DEPTH};
+
+const SHADER_PATH: &str = "postproc/main.wgsl";
+
 pub struct PostProcessingPass {
-    pipeline: RenderPipeline,
-    bind_group: BindGroup,
+    pipeline: PassPipeline<RenderPipeline>,
 }
 
 impl PostProcessingPass {
-    //TODO: Support resizing
-    pub fn new(ctx: &GraphicsCtx, (width, height): (u32, u32)) -> (Self, TextureView) {
-        let shader = ctx
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(
-                    load_wgsl_with_preprocessor("wgsl/postproc/main.wgsl").into(),
-                ),
-            });
-        let bind_group_layout =
-            ctx.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Texture {
-                                multisampled: false,
-                                // Should filterable be false if we want nearest-neighbor?
-                                sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::FRAGMENT,
-                            ty: wgpu::BindingType::Sampler(SamplerBindingType::NonFiltering),
-                            count: None,
-                        },
-                    ],
-                });
-        let pipeline_layout = ctx
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let pipeline = ctx
-            .device
-            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                vertex: wgpu::VertexState {
-                    module: &shader,
-                    entry_point: "vs_main",
-                    buffers: &[],
-                    compilation_options: PipelineCompilationOptions::default(),
-                },
-                fragment: Some(wgpu::FragmentState {
-                    module: &shader,
-                    entry_point: "fs_main",
-                    targets: &[Some(ctx.surface_texture_format.into())],
-                    compilation_options: PipelineCompilationOptions::default(),
-                }),
-                primitive: wgpu::PrimitiveState::default(),
-                depth_stencil: None,
-                multisample: wgpu::MultisampleState::default(),
-                multiview: None,
-            });
-
-        let input = ctx.device.create_texture(&wgpu::TextureDescriptor {
-            label: None,
-            size: Extent3d {
-                width: width,
-                height: height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba8Unorm,
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
-            view_formats: &[],
-        });
-        let tex_view = input.create_view(&Default::default());
+    pub fn new(ctx: &GraphicsCtx) -> Result<Self, ShaderError> {
+        Self::with_defines(ctx, Defines::new())
+    }
 
+    /// Creates the pass with preprocessor defines used to compile shader variants.
+    pub fn with_defines(ctx: &GraphicsCtx, defines: Defines) -> Result<Self, ShaderError> {
         let sampler = ctx.device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
@@ -96,49 +34,43 @@ impl PostProcessingPass {
             mipmap_filter: wgpu::FilterMode::Nearest,
             ..Default::default()
         });
-        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&tex_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-            ],
-        });
 
-        (
-            Self {
-                pipeline,
-                bind_group,
-            },
-            tex_view,
-        )
+        let pipeline = PipelineBuilder::fullscreen(SHADER_PATH)
+            .with_defines(defines)
+            // Should filterable be false if we want nearest-neighbor?
+            .with_binding(Binding::texture(VOXEL_COLOR, true))
+            .with_binding(Binding::sampler(sampler, SamplerBindingType::NonFiltering))
+            .with_binding(Binding::texture(VOXEL_DEPTH, false))
+            .build(ctx)?;
+
+        Ok(Self { pipeline })
+    }
+}
+
+impl RenderPass for PostProcessingPass {
+    fn name(&self) -> &'static str {
+        "postproc"
+    }
+
+    fn io(&self) -> PassIo {
+        PassIo::new()
+            .read_texture(VOXEL_COLOR, TextureUsages::TEXTURE_BINDING)
+            .read_texture(VOXEL_DEPTH, TextureUsages::TEXTURE_BINDING)
+    }
+
+    fn refresh(&mut self, ctx: &GraphicsCtx) -> Result<(), ShaderError> {
+        self.pipeline.refresh(ctx)
+    }
+
+    fn shader_files(&self) -> &[PathBuf] {
+        self.pipeline.shader_files()
+    }
+
+    fn bind(&mut self, ctx: &GraphicsCtx, resources: &Resources) {
+        self.pipeline.bind(ctx, resources);
     }
 
-    pub fn run(&self, frame: &mut Frame) {
-        let mut rpass = frame
-            .render
-            .encoder
-            .begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &frame.render.view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::GREEN),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                ..Default::default()
-            });
-        rpass.set_pipeline(&self.pipeline);
-        rpass.set_bind_group(0, &self.bind_group, &[]);
-        rpass.draw(0..3, 0..2);
+    fn run(&mut self, frame: &mut Frame, _: &Resources, _: &FrameInputs) {
+        self.pipeline.draw(frame);
     }
 }
@@ -1,177 +1,131 @@
-use std::{mem::size_of, num::NonZeroU64};
+use std::path::PathBuf;
 
 use crate::{
+    gpu_struct,
     graphics::{
-        camera::{Camera, CameraRenderParams},
+        buffer::UniformBuffer,
+        camera::CameraRenderParams,
         ctx::GraphicsCtx,
-        wgsl::load_wgsl_with_preprocessor,
+        graph::{FrameInputs, PassIo, RenderPass, Resources, TextureDesc},
+        pipeline::{Binding, PassPipeline, PipelineBuilder},
+        scene::Scene,
+        shader::ShaderError,
+        wgsl::Defines,
         Frame,
     },
-    maths::{Vec2f, Vec2u},
+    maths::Vec2u,
 };
 use bytemuck::{Pod, Zeroable};
-use util::{DeviceExt, StagingBelt};
 use wgpu::*;
 
+const SHADER_PATH: &str = "voxel/main.wgsl";
+
+/// Shaded color of each pixel.
+pub const VOXEL_COLOR: &str = "voxel.color";
+/// Distance to the hit voxel, negative when the ray escaped.
+pub const VOXEL_DEPTH: &str = "voxel.depth";
+
 pub struct VoxelRenderingPass {
-    pipeline: ComputePipeline,
-    params: Buffer,
-    camera_params: Buffer,
-    bind_group: BindGroup,
-    staging_belt: StagingBelt,
+    pipeline: PassPipeline<ComputePipeline>,
+    params: UniformBuffer<VoxelPassParams>,
+    camera_params: UniformBuffer<CameraRenderParams>,
 }
 
-const PARAMS_SIZE: u64 = size_of::<VoxelPassParams>() as u64;
-const CAMERA_PARAMS_SIZE: u64 = size_of::<CameraRenderParams>() as u64;
-
 impl VoxelRenderingPass {
-    pub fn new(ctx: &GraphicsCtx, output: TextureView) -> Self {
-        let shader = ctx
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: wgpu::ShaderSource::Wgsl(
-                    load_wgsl_with_preprocessor("wgsl/voxel/main.wgsl").into(),
-                ),
-            });
-        let bind_group_layout =
-            ctx.device
-                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                    label: None,
-                    entries: &[
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 0,
-                            visibility: wgpu::ShaderStages::COMPUTE,
-                            ty: wgpu::BindingType::StorageTexture {
-                                access: wgpu::StorageTextureAccess::WriteOnly,
-                                format: wgpu::TextureFormat::Rgba8Unorm,
-                                view_dimension: wgpu::TextureViewDimension::D2,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 1,
-                            visibility: wgpu::ShaderStages::COMPUTE,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                        wgpu::BindGroupLayoutEntry {
-                            binding: 2,
-                            visibility: wgpu::ShaderStages::COMPUTE,
-                            ty: wgpu::BindingType::Buffer {
-                                ty: wgpu::BufferBindingType::Uniform,
-                                has_dynamic_offset: false,
-                                min_binding_size: None,
-                            },
-                            count: None,
-                        },
-                    ],
-                });
-        let pipeline = ctx
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let pipeline = ctx
-            .device
-            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline),
-                module: &shader,
-                entry_point: "main",
-                compilation_options: PipelineCompilationOptions::default(),
-            });
-
-        let params = ctx.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: PARAMS_SIZE,
-            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
-            mapped_at_creation: false,
-        });
-        let params_binding = params.as_entire_binding();
-
-        let camera_params = ctx.device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: CAMERA_PARAMS_SIZE,
-            usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
-            mapped_at_creation: false,
-        });
-        let camera_params_binding = camera_params.as_entire_binding();
-
-        let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout: &bind_group_layout,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&output),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: params_binding,
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: camera_params_binding,
-                },
-            ],
-        });
-
-        let staging_belt = StagingBelt::new(PARAMS_SIZE + CAMERA_PARAMS_SIZE); //TODO: improve this?
-
-        Self {
+    pub fn new(ctx: &GraphicsCtx) -> Result<Self, ShaderError> {
+        Self::with_defines(ctx, Defines::new())
+    }
+
+    /// Creates the pass with preprocessor defines used to compile shader variants.
+    pub fn with_defines(ctx: &GraphicsCtx, defines: Defines) -> Result<Self, ShaderError> {
+        Self::with_scene(ctx, &Scene::default(), defines)
+    }
+
+    /// Creates the pass tracing `scene`, which must be registered in the shader files of `ctx`
+    /// when given as a source.
+    pub fn with_scene(
+        ctx: &GraphicsCtx,
+        scene: &Scene,
+        mut defines: Defines,
+    ) -> Result<Self, ShaderError> {
+        defines.extend(scene.defines());
+        let params = UniformBuffer::new(ctx);
+        let camera_params = UniformBuffer::new(ctx);
+
+        let pipeline = PipelineBuilder::compute(SHADER_PATH)
+            .with_defines(defines)
+            .with_binding(Binding::storage_texture(
+                VOXEL_COLOR,
+                TextureFormat::Rgba8Unorm,
+            ))
+            .with_binding(Binding::uniform(&params))
+            .with_binding(Binding::uniform(&camera_params))
+            .with_binding(Binding::storage_texture(
+                VOXEL_DEPTH,
+                TextureFormat::R32Float,
+            ))
+            .check_layout::<VoxelPassParams>()
+            .check_layout::<CameraRenderParams>()
+            .build(ctx)?;
+
+        Ok(Self {
             pipeline,
             params,
             camera_params,
-            bind_group,
-            staging_belt,
-        }
+        })
+    }
+}
+
+impl RenderPass for VoxelRenderingPass {
+    fn name(&self) -> &'static str {
+        "voxel"
     }
 
-    pub fn run(&mut self, frame: &mut Frame, camera: &Camera, params: VoxelPassParams) {
-        self.staging_belt
-            .write_buffer(
-                &mut frame.render.encoder,
-                &self.params,
-                0,
-                NonZeroU64::new(PARAMS_SIZE).unwrap(),
-                &frame.ctx.device,
+    fn io(&self) -> PassIo {
+        PassIo::new()
+            .write_texture(
+                VOXEL_COLOR,
+                TextureDesc::window(TextureFormat::Rgba8Unorm),
+                TextureUsages::STORAGE_BINDING,
             )
-            .copy_from_slice(bytemuck::bytes_of(&params));
-
-        self.staging_belt
-            .write_buffer(
-                &mut frame.render.encoder,
-                &self.camera_params,
-                0,
-                NonZeroU64::new(CAMERA_PARAMS_SIZE).unwrap(),
-                &frame.ctx.device,
+            .write_texture(
+                VOXEL_DEPTH,
+                TextureDesc::window(TextureFormat::R32Float),
+                TextureUsages::STORAGE_BINDING,
             )
-            .copy_from_slice(bytemuck::bytes_of(
-                &camera.render_params(Vec2u::new(params.width, params.height)),
-            ));
-
-        self.staging_belt.finish();
-        {
-            let mut cpass = frame.render.encoder.begin_compute_pass(&Default::default());
-            cpass.set_pipeline(&self.pipeline);
-            cpass.set_bind_group(0, &self.bind_group, &[]);
-            cpass.dispatch_workgroups(params.width / 16, params.height / 16, 1);
-        }
     }
 
-    pub fn post_render(&mut self) {
-        self.staging_belt.recall();
+    fn refresh(&mut self, ctx: &GraphicsCtx) -> Result<(), ShaderError> {
+        self.pipeline.refresh(ctx)
+    }
+
+    fn shader_files(&self) -> &[PathBuf] {
+        self.pipeline.shader_files()
     }
-}
 
-const CONFIG_SIZE: u64 = size_of::<VoxelPassParams>() as u64;
+    fn bind(&mut self, ctx: &GraphicsCtx, resources: &Resources) {
+        self.pipeline.bind(ctx, resources);
+    }
+
+    fn run(&mut self, frame: &mut Frame, _: &Resources, inputs: &FrameInputs) {
+        let (width, height) = frame.size;
+        let params = VoxelPassParams {
+            time: inputs.time,
+            width,
+            height,
+            debug_view: inputs.debug_view as u32,
+        };
+
+        self.params.write(frame.ctx, &params);
+        self.camera_params.write(
+            frame.ctx,
+            &inputs.camera.render_params(Vec2u::new(width, height)),
+        );
+
+        self.pipeline
+            .dispatch(frame, (width.div_ceil(16), height.div_ceil(16), 1));
+    }
+}
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
@@ -179,4 +133,47 @@ pub struct VoxelPassParams {
     pub width: u32,
     pub height: u32,
     pub time: f32,
+    pub debug_view: u32,
+}
+
+gpu_struct!(VoxelPassParams as "Params" {
+    width,
+    height,
+    time,
+    debug_view,
+});
+
+/// What the voxel pass outputs instead of the shaded color, used to diagnose traversal issues.
+/// Discriminants must match the `DEBUG_VIEW_*` constants in `wgsl/voxel/main.wgsl`.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DebugView {
+    #[default]
+    Shaded = 0,
+    Normals = 1,
+    Depth = 2,
+    /// Heatmap of the number of DDA steps taken before hitting or giving up.
+    Steps = 3,
+    Materials = 4,
+    /// Voxel and chunk grid lines drawn over the hit surface.
+    Boundaries = 5,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 6] = [
+        DebugView::Shaded,
+        DebugView::Normals,
+        DebugView::Depth,
+        DebugView::Steps,
+        DebugView::Materials,
+        DebugView::Boundaries,
+    ];
+
+    pub fn next(self) -> Self {
+        Self::ALL[(self as usize + 1) % Self::ALL.len()]
+    }
+
+    pub fn previous(self) -> Self {
+        Self::ALL[(self as usize + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
 }
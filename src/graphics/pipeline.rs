@@ -0,0 +1,467 @@
+use std::{marker::PhantomData, path::PathBuf, sync::Arc};
+
+use bytemuck::Pod;
+use wgpu::*;
+
+use super::{
+    buffer::{StorageBuffer, UniformBuffer},
+    ctx::GraphicsCtx,
+    graph::{ResourceId, Resources},
+    layout::GpuStruct,
+    pipeline_cache::cache_key,
+    shader::{Shader, ShaderError},
+    wgsl::Defines,
+    Frame,
+};
+
+/// A resource bound to a pass pipeline.
+pub enum Binding {
+    /// Write-only storage texture from the render graph.
+    StorageTexture {
+        resource: ResourceId,
+        format: TextureFormat,
+    },
+    /// Sampled float texture from the render graph.
+    Texture {
+        resource: ResourceId,
+        filterable: bool,
+    },
+    /// Storage buffer from the render graph.
+    GraphBuffer {
+        resource: ResourceId,
+        read_only: bool,
+    },
+    Sampler {
+        sampler: Sampler,
+        ty: SamplerBindingType,
+    },
+    Uniform {
+        buffer: Arc<Buffer>,
+        layout: BindGroupLayoutEntry,
+    },
+    Storage {
+        buffer: Arc<Buffer>,
+        layout: BindGroupLayoutEntry,
+    },
+}
+
+impl Binding {
+    pub fn storage_texture(resource: ResourceId, format: TextureFormat) -> Self {
+        Binding::StorageTexture { resource, format }
+    }
+
+    pub fn texture(resource: ResourceId, filterable: bool) -> Self {
+        Binding::Texture {
+            resource,
+            filterable,
+        }
+    }
+
+    pub fn graph_buffer(resource: ResourceId, read_only: bool) -> Self {
+        Binding::GraphBuffer {
+            resource,
+            read_only,
+        }
+    }
+
+    pub fn sampler(sampler: Sampler, ty: SamplerBindingType) -> Self {
+        Binding::Sampler { sampler, ty }
+    }
+
+    pub fn uniform<T: Pod>(buffer: &UniformBuffer<T>) -> Self {
+        Binding::Uniform {
+            buffer: buffer.buffer.clone(),
+            layout: UniformBuffer::<T>::layout_entry(0, ShaderStages::NONE),
+        }
+    }
+
+    pub fn storage<T: Pod>(buffer: &StorageBuffer<T>, read_only: bool) -> Self {
+        Binding::Storage {
+            buffer: buffer.buffer.clone(),
+            layout: StorageBuffer::<T>::layout_entry(0, ShaderStages::NONE, read_only),
+        }
+    }
+
+    fn layout_entry(&self, binding: u32, visibility: ShaderStages) -> BindGroupLayoutEntry {
+        let ty = match self {
+            Binding::StorageTexture { format, .. } => BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format: *format,
+                view_dimension: TextureViewDimension::D2,
+            },
+            Binding::Texture { filterable, .. } => BindingType::Texture {
+                multisampled: false,
+                sample_type: TextureSampleType::Float {
+                    filterable: *filterable,
+                },
+                view_dimension: TextureViewDimension::D2,
+            },
+            Binding::GraphBuffer { read_only, .. } => BindingType::Buffer {
+                ty: BufferBindingType::Storage {
+                    read_only: *read_only,
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            Binding::Sampler { ty, .. } => BindingType::Sampler(*ty),
+            Binding::Uniform { layout, .. } | Binding::Storage { layout, .. } => layout.ty,
+        };
+
+        BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        }
+    }
+
+    fn resource<'a>(&'a self, resources: &'a Resources) -> BindingResource<'a> {
+        match self {
+            Binding::StorageTexture { resource, .. } | Binding::Texture { resource, .. } => {
+                BindingResource::TextureView(resources.texture_view(resource))
+            }
+            Binding::GraphBuffer { resource, .. } => resources.buffer(resource).as_entire_binding(),
+            Binding::Sampler { sampler, .. } => BindingResource::Sampler(sampler),
+            Binding::Uniform { buffer, .. } | Binding::Storage { buffer, .. } => {
+                buffer.as_entire_binding()
+            }
+        }
+    }
+}
+
+/// Kind of pipeline a [`PassPipeline`] wraps.
+pub trait PipelineKind: Sized + Send + Sync + 'static {
+    const VISIBILITY: ShaderStages;
+
+    fn create(
+        ctx: &GraphicsCtx,
+        desc: &PipelineDesc,
+        module: &ShaderModule,
+        layout: &PipelineLayout,
+    ) -> Self;
+}
+
+impl PipelineKind for ComputePipeline {
+    const VISIBILITY: ShaderStages = ShaderStages::COMPUTE;
+
+    fn create(
+        ctx: &GraphicsCtx,
+        desc: &PipelineDesc,
+        module: &ShaderModule,
+        layout: &PipelineLayout,
+    ) -> Self {
+        ctx.device
+            .create_compute_pipeline(&ComputePipelineDescriptor {
+                label: desc.path.to_str(),
+                layout: Some(layout),
+                module,
+                entry_point: desc.entry_point,
+                compilation_options: PipelineCompilationOptions::default(),
+            })
+    }
+}
+
+/// Draws a quad covering the target, the vertex shader positions it from the vertex and
+/// instance indices.
+impl PipelineKind for RenderPipeline {
+    const VISIBILITY: ShaderStages = ShaderStages::FRAGMENT;
+
+    fn create(
+        ctx: &GraphicsCtx,
+        desc: &PipelineDesc,
+        module: &ShaderModule,
+        layout: &PipelineLayout,
+    ) -> Self {
+        ctx.device
+            .create_render_pipeline(&RenderPipelineDescriptor {
+                label: desc.path.to_str(),
+                layout: Some(layout),
+                vertex: VertexState {
+                    module,
+                    entry_point: desc.vertex_entry_point,
+                    buffers: &[],
+                    compilation_options: PipelineCompilationOptions::default(),
+                },
+                fragment: Some(FragmentState {
+                    module,
+                    entry_point: desc.entry_point,
+                    targets: &[Some(ctx.surface_texture_format.into())],
+                    compilation_options: PipelineCompilationOptions::default(),
+                }),
+                primitive: PrimitiveState::default(),
+                depth_stencil: None,
+                multisample: MultisampleState::default(),
+                multiview: None,
+            })
+    }
+}
+
+/// Checks a struct against the shader, with the name of the struct to tell checks apart.
+type LayoutCheck = (&'static str, fn(&Shader) -> Result<(), ShaderError>);
+
+/// Everything needed to (re)create a pass pipeline.
+pub struct PipelineDesc {
+    pub path: PathBuf,
+    pub defines: Defines,
+    /// Compute or fragment entry point.
+    pub entry_point: &'static str,
+    /// Unused by compute pipelines.
+    pub vertex_entry_point: &'static str,
+    /// Numbered in order.
+    pub bindings: Vec<Binding>,
+    layout_checks: Vec<LayoutCheck>,
+}
+
+/// Builds a [`PassPipeline`].
+///
+/// ```ignore
+/// let pipeline = PipelineBuilder::compute("voxel/main.wgsl")
+///     .with_binding(Binding::storage_texture(VOXEL_COLOR, TextureFormat::Rgba8Unorm))
+///     .with_binding(Binding::uniform(&params))
+///     .check_layout::<VoxelPassParams>()
+///     .build(ctx)?;
+/// ```
+pub struct PipelineBuilder<P> {
+    desc: PipelineDesc,
+    _kind: PhantomData<P>,
+}
+
+impl PipelineBuilder<ComputePipeline> {
+    /// Compute pipeline, with a `main` entry point by default.
+    pub fn compute(path: impl Into<PathBuf>) -> Self {
+        Self::new(path.into(), "main")
+    }
+}
+
+impl PipelineBuilder<RenderPipeline> {
+    /// Fullscreen render pipeline, with `vs_main` and `fs_main` entry points by default.
+    pub fn fullscreen(path: impl Into<PathBuf>) -> Self {
+        Self::new(path.into(), "fs_main")
+    }
+
+    pub fn with_vertex_entry_point(mut self, entry_point: &'static str) -> Self {
+        self.desc.vertex_entry_point = entry_point;
+        self
+    }
+}
+
+impl<P: PipelineKind> PipelineBuilder<P> {
+    fn new(path: PathBuf, entry_point: &'static str) -> Self {
+        Self {
+            desc: PipelineDesc {
+                path,
+                defines: Defines::new(),
+                entry_point,
+                vertex_entry_point: "vs_main",
+                bindings: Vec::new(),
+                layout_checks: Vec::new(),
+            },
+            _kind: PhantomData,
+        }
+    }
+
+    pub fn with_entry_point(mut self, entry_point: &'static str) -> Self {
+        self.desc.entry_point = entry_point;
+        self
+    }
+
+    pub fn with_defines(mut self, defines: Defines) -> Self {
+        self.desc.defines = defines;
+        self
+    }
+
+    /// Adds the next binding of group 0.
+    pub fn with_binding(mut self, binding: Binding) -> Self {
+        self.desc.bindings.push(binding);
+        self
+    }
+
+    /// Checks `T` against its WGSL struct whenever the shader is loaded.
+    pub fn check_layout<T: GpuStruct>(mut self) -> Self {
+        self.desc
+            .layout_checks
+            .push((std::any::type_name::<T>(), Shader::check_layout::<T>));
+        self
+    }
+
+    pub fn build(self, ctx: &GraphicsCtx) -> Result<PassPipeline<P>, ShaderError> {
+        Ok(PassPipeline {
+            compiled: create(ctx, &self.desc)?,
+            desc: self.desc,
+            bind_group: None,
+        })
+    }
+}
+
+/// A pipeline with its bind group, recreated when the shader is refreshed or the graph
+/// resources change.
+pub struct PassPipeline<P> {
+    desc: PipelineDesc,
+    compiled: Compiled<P>,
+    bind_group: Option<BindGroup>,
+}
+
+/// Objects shared with the pipeline cache.
+struct Compiled<P> {
+    pipeline: Arc<P>,
+    bind_group_layout: Arc<BindGroupLayout>,
+    shader_files: Vec<PathBuf>,
+}
+
+impl<P: PipelineKind> PassPipeline<P> {
+    /// Reloads the shader, keeping the previous pipeline on failure.
+    pub fn refresh(&mut self, ctx: &GraphicsCtx) -> Result<(), ShaderError> {
+        self.compiled = create(ctx, &self.desc)?;
+        Ok(())
+    }
+
+    pub fn bind(&mut self, ctx: &GraphicsCtx, resources: &Resources) {
+        let entries: Vec<_> = self
+            .desc
+            .bindings
+            .iter()
+            .enumerate()
+            .map(|(i, binding)| BindGroupEntry {
+                binding: i as u32,
+                resource: binding.resource(resources),
+            })
+            .collect();
+
+        self.bind_group = Some(ctx.device.create_bind_group(&BindGroupDescriptor {
+            label: self.desc.path.to_str(),
+            layout: &self.compiled.bind_group_layout,
+            entries: &entries,
+        }));
+    }
+
+    pub fn shader_files(&self) -> &[PathBuf] {
+        &self.compiled.shader_files
+    }
+
+    pub fn pipeline(&self) -> &P {
+        &self.compiled.pipeline
+    }
+
+    pub fn bind_group(&self) -> Option<&BindGroup> {
+        self.bind_group.as_ref()
+    }
+}
+
+impl PassPipeline<ComputePipeline> {
+    /// Dispatches the pipeline, does nothing until bound.
+    pub fn dispatch(&self, frame: &mut Frame, workgroups: (u32, u32, u32)) {
+        self.encode_dispatch(&mut frame.render.encoder, workgroups);
+    }
+
+    /// Records the dispatch in `encoder`, for work submitted outside of a frame.
+    pub fn encode_dispatch(&self, encoder: &mut CommandEncoder, workgroups: (u32, u32, u32)) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+
+        let mut cpass = encoder.begin_compute_pass(&Default::default());
+        cpass.set_pipeline(&self.compiled.pipeline);
+        cpass.set_bind_group(0, bind_group, &[]);
+        cpass.dispatch_workgroups(workgroups.0, workgroups.1, workgroups.2);
+    }
+}
+
+impl PassPipeline<RenderPipeline> {
+    /// Draws over the whole frame target, does nothing until bound.
+    pub fn draw(&self, frame: &mut Frame) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+
+        let mut rpass = frame
+            .render
+            .encoder
+            .begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &frame.render.view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::GREEN),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                ..Default::default()
+            });
+        rpass.set_pipeline(&self.compiled.pipeline);
+        rpass.set_bind_group(0, bind_group, &[]);
+        rpass.draw(0..3, 0..2);
+    }
+}
+
+/// Creates the pipeline, or reuses it from the cache when the preprocessed shader and the
+/// pipeline description are unchanged.
+fn create<P: PipelineKind>(
+    ctx: &GraphicsCtx,
+    desc: &PipelineDesc,
+) -> Result<Compiled<P>, ShaderError> {
+    let wgsl = Shader::preprocess(ctx, &desc.path, &desc.defines)?;
+    let shader_files = wgsl.files().to_vec();
+
+    let entries: Vec<_> = desc
+        .bindings
+        .iter()
+        .enumerate()
+        .map(|(i, binding)| binding.layout_entry(i as u32, P::VISIBILITY))
+        .collect();
+    let key = cache_key((
+        &wgsl.source,
+        &desc.defines,
+        desc.entry_point,
+        desc.vertex_entry_point,
+        &entries,
+        // Reusing a pipeline skips the checks, which could differ for the same shader.
+        desc.layout_checks
+            .iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>(),
+        std::any::type_name::<P>(),
+        ctx.surface_texture_format,
+    ));
+    if let Some((pipeline, bind_group_layout)) = ctx.pipelines.get(key) {
+        return Ok(Compiled {
+            pipeline,
+            bind_group_layout,
+            shader_files,
+        });
+    }
+
+    let shader = Shader::compile(ctx, &desc.path, wgsl)?;
+    for (_, check) in &desc.layout_checks {
+        check(&shader)?;
+    }
+
+    let (pipeline, bind_group_layout) = shader.create_pipeline(ctx, |module| {
+        let bind_group_layout = ctx
+            .device
+            .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: desc.path.to_str(),
+                entries: &entries,
+            });
+        let pipeline_layout = ctx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: desc.path.to_str(),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        (
+            Arc::new(P::create(ctx, desc, module, &pipeline_layout)),
+            Arc::new(bind_group_layout),
+        )
+    })?;
+    ctx.pipelines.insert(key, &pipeline, &bind_group_layout);
+
+    Ok(Compiled {
+        pipeline,
+        bind_group_layout,
+        shader_files,
+    })
+}
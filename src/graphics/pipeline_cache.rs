@@ -0,0 +1,68 @@
+use std::{
+    any::Any,
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex, Weak},
+};
+
+use wgpu::BindGroupLayout;
+
+/// Hash identifying a pipeline compiled from a preprocessed shader.
+pub type CacheKey = u64;
+
+/// Hashes `value`, keys are only meaningful within a run.
+pub fn cache_key(value: impl Hash) -> CacheKey {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Compiled pipelines shared by every pass, so unchanged shaders and identical variants are only
+/// compiled once.
+///
+/// Pipelines are kept alive by the passes using them, the cache only holds weak references.
+/// Nothing is persisted across runs: wgpu 0.20 has no API to save or load the driver pipeline
+/// cache.
+#[derive(Default)]
+pub struct PipelineCache {
+    pipelines: Mutex<HashMap<CacheKey, CachedPipeline>>,
+}
+
+struct CachedPipeline {
+    pipeline: Weak<dyn Any + Send + Sync>,
+    bind_group_layout: Weak<BindGroupLayout>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get<P: Any + Send + Sync>(
+        &self,
+        key: CacheKey,
+    ) -> Option<(Arc<P>, Arc<BindGroupLayout>)> {
+        let pipelines = self.pipelines.lock().unwrap();
+        let cached = pipelines.get(&key)?;
+        let pipeline = cached.pipeline.upgrade()?.downcast().ok()?;
+        Some((pipeline, cached.bind_group_layout.upgrade()?))
+    }
+
+    pub fn insert<P: Any + Send + Sync>(
+        &self,
+        key: CacheKey,
+        pipeline: &Arc<P>,
+        bind_group_layout: &Arc<BindGroupLayout>,
+    ) {
+        let pipeline: Arc<dyn Any + Send + Sync> = pipeline.clone();
+        let mut pipelines = self.pipelines.lock().unwrap();
+        pipelines.retain(|_, cached| cached.pipeline.strong_count() > 0);
+        pipelines.insert(
+            key,
+            CachedPipeline {
+                pipeline: Arc::downgrade(&pipeline),
+                bind_group_layout: Arc::downgrade(bind_group_layout),
+            },
+        );
+    }
+}
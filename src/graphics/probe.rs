@@ -0,0 +1,304 @@
+use std::{fmt, path::PathBuf};
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::ComputePipeline;
+
+use super::{
+    buffer::{StorageBuffer, UniformBuffer},
+    ctx::GraphicsCtx,
+    graph::Resources,
+    pipeline::{Binding, PassPipeline, PipelineBuilder},
+    scene::{Scene, VOXEL_SIZE},
+    shader::ShaderError,
+};
+use crate::{
+    gpu_struct,
+    maths::{Vec3f, Vec3i, Vec3u},
+};
+
+const SHADER_PATH: &str = "voxel/probe.wgsl";
+
+/// Most voxels sampled at once.
+pub const MAX_PROBE_VOXELS: usize = 64 * 64 * 64;
+/// Voxels probed on every side of the requested box, so it can move a while before being probed
+/// again.
+const PROBE_MARGIN: u32 = 16;
+/// Scene seconds probed voxels are reused for, so animated scenes aren't read back on every
+/// update. Collisions can lag this much behind the rendered scene.
+const MAX_PROBE_AGE: f32 = 0.1;
+
+/// Voxels a body can collide with.
+pub trait VoxelWorld {
+    fn is_solid(&self, voxel: Vec3i) -> bool;
+}
+
+/// Materials of a box of voxels read back from the scene, voxels outside of it are empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VoxelGrid {
+    /// Coordinates of the first voxel, in voxels.
+    pub origin: Vec3i,
+    pub size: Vec3u,
+    /// Materials ordered by x, then y, then z, `0` for empty voxels.
+    pub materials: Vec<u32>,
+}
+
+impl VoxelGrid {
+    /// Whether the box of `size` voxels starting at `origin` is inside the grid.
+    pub fn contains(&self, origin: Vec3i, size: Vec3u) -> bool {
+        (0..3).all(|axis| {
+            let start = origin[axis] as i64 - self.origin[axis] as i64;
+            start >= 0 && start + size[axis] as i64 <= self.size[axis] as i64
+        })
+    }
+
+    pub fn material(&self, voxel: Vec3i) -> u32 {
+        let local = voxel - self.origin;
+        let inside = [local.x, local.y, local.z]
+            .iter()
+            .zip([self.size.x, self.size.y, self.size.z])
+            .all(|(&coord, size)| coord >= 0 && (coord as u32) < size);
+        if !inside {
+            return 0;
+        }
+
+        let (x, y, z) = (local.x as usize, local.y as usize, local.z as usize);
+        let (width, height) = (self.size.x as usize, self.size.y as usize);
+        self.materials[x + width * (y + height * z)]
+    }
+}
+
+impl VoxelWorld for VoxelGrid {
+    fn is_solid(&self, voxel: Vec3i) -> bool {
+        self.material(voxel) != 0
+    }
+}
+
+/// Whether `grid` probed at `probe_time` can stand for the `size` voxels starting at `origin` at
+/// `time`.
+fn reusable(grid: &VoxelGrid, probe_time: f32, origin: Vec3i, size: Vec3u, time: f32) -> bool {
+    (time - probe_time).abs() <= MAX_PROBE_AGE && grid.contains(origin, size)
+}
+
+/// Voxel containing the world position `position`.
+pub fn voxel_at(position: Vec3f) -> Vec3i {
+    (position / VOXEL_SIZE).map(|coord| coord.floor() as i32)
+}
+
+/// Evaluates the materials of the scene on the GPU so they can be used on the CPU.
+pub struct VoxelProbe {
+    pipeline: PassPipeline<ComputePipeline>,
+    params: UniformBuffer<ProbeParams>,
+    materials: StorageBuffer<u32>,
+    /// Last box probed by [`VoxelProbe::sample_around`] and its scene time.
+    cached: Option<(VoxelGrid, f32)>,
+}
+
+impl VoxelProbe {
+    /// Creates the probe sampling `scene`, which must be registered in the shader files of `ctx`
+    /// when given as a source.
+    pub fn new(ctx: &GraphicsCtx, scene: &Scene) -> Result<Self, ShaderError> {
+        let params = UniformBuffer::new(ctx);
+        let materials = StorageBuffer::new(ctx, MAX_PROBE_VOXELS);
+
+        let mut pipeline = PipelineBuilder::compute(SHADER_PATH)
+            .with_defines(scene.defines())
+            .with_binding(Binding::uniform(&params))
+            .with_binding(Binding::storage(&materials, false))
+            .check_layout::<ProbeParams>()
+            .build(ctx)?;
+        pipeline.bind(ctx, &Resources::default());
+
+        Ok(Self {
+            pipeline,
+            params,
+            materials,
+            cached: None,
+        })
+    }
+
+    /// Reads back the materials of the `size` voxels starting at `origin` at `time`, blocking
+    /// until the GPU is done. Fails when there are more than [`MAX_PROBE_VOXELS`].
+    pub fn sample(
+        &self,
+        ctx: &GraphicsCtx,
+        origin: Vec3i,
+        size: Vec3u,
+        time: f32,
+    ) -> Result<VoxelGrid, ProbeError> {
+        let len = [size.x, size.y, size.z]
+            .into_iter()
+            .try_fold(1usize, |len, extent| len.checked_mul(extent as usize))
+            .filter(|&len| len <= MAX_PROBE_VOXELS)
+            .ok_or(ProbeError::TooManyVoxels { size })?;
+
+        self.params.write(
+            ctx,
+            &ProbeParams {
+                origin: origin.into(),
+                time,
+                size: size.into(),
+                __padding: f32::NAN,
+            },
+        );
+
+        let mut encoder = ctx.device.create_command_encoder(&Default::default());
+        self.pipeline.encode_dispatch(
+            &mut encoder,
+            (size.x.div_ceil(4), size.y.div_ceil(4), size.z.div_ceil(4)),
+        );
+        ctx.queue.submit(std::iter::once(encoder.finish()));
+
+        Ok(VoxelGrid {
+            origin,
+            size,
+            materials: self.materials.read(ctx, len),
+        })
+    }
+
+    /// Same as [`VoxelProbe::sample`] with a margin around the box, which is kept and returned
+    /// again without reading it back while the box stays inside and `time` is within
+    /// [`MAX_PROBE_AGE`] of the time it was probed at, the returned voxels may be slightly stale.
+    pub fn sample_around(
+        &mut self,
+        ctx: &GraphicsCtx,
+        origin: Vec3i,
+        size: Vec3u,
+        time: f32,
+    ) -> Result<&VoxelGrid, ProbeError> {
+        let hit = self
+            .cached
+            .as_ref()
+            .is_some_and(|(grid, probe_time)| reusable(grid, *probe_time, origin, size, time));
+        if !hit {
+            let margin = Vec3u::new(PROBE_MARGIN, PROBE_MARGIN, PROBE_MARGIN);
+            let padded = self.sample(
+                ctx,
+                origin - margin.map(|margin| margin as i32),
+                size.map(|extent| extent.saturating_add(2 * PROBE_MARGIN)),
+                time,
+            );
+            // Large boxes may only fit without the margin.
+            let grid = match padded {
+                Ok(grid) => grid,
+                Err(ProbeError::TooManyVoxels { .. }) => self.sample(ctx, origin, size, time)?,
+            };
+            self.cached = Some((grid, time));
+        }
+
+        Ok(&self.cached.as_ref().unwrap().0)
+    }
+
+    pub fn refresh(&mut self, ctx: &GraphicsCtx) -> Result<(), ShaderError> {
+        self.pipeline.refresh(ctx)?;
+        self.pipeline.bind(ctx, &Resources::default());
+        self.cached = None;
+        Ok(())
+    }
+
+    pub fn shader_files(&self) -> &[PathBuf] {
+        self.pipeline.shader_files()
+    }
+}
+
+#[derive(Debug)]
+pub enum ProbeError {
+    /// The box has more than [`MAX_PROBE_VOXELS`].
+    TooManyVoxels { size: Vec3u },
+}
+
+impl fmt::Display for ProbeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeError::TooManyVoxels { size } => write!(
+                f,
+                "Cannot probe {}x{}x{} voxels, at most {MAX_PROBE_VOXELS} are probed at once",
+                size.x, size.y, size.z
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProbeError {}
+
+#[repr(C)]
+#[derive(Copy, Clone, Pod, Zeroable)]
+struct ProbeParams {
+    origin: [i32; 3],
+    time: f32,
+    size: [u32; 3],
+    __padding: f32,
+}
+
+gpu_struct!(ProbeParams as "ProbeParams" {
+    origin,
+    time,
+    size,
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> VoxelGrid {
+        VoxelGrid {
+            origin: Vec3i::new(-1, 0, 2),
+            size: Vec3u::new(2, 3, 4),
+            materials: (1..=24).collect(),
+        }
+    }
+
+    #[test]
+    fn materials_are_ordered_by_x_then_y_then_z() {
+        let grid = grid();
+        assert_eq!(grid.material(Vec3i::new(-1, 0, 2)), 1);
+        assert_eq!(grid.material(Vec3i::new(0, 0, 2)), 2);
+        assert_eq!(grid.material(Vec3i::new(-1, 1, 2)), 3);
+        assert_eq!(grid.material(Vec3i::new(-1, 0, 3)), 7);
+        assert_eq!(grid.material(Vec3i::new(0, 2, 5)), 24);
+        assert_eq!(grid.material(Vec3i::new(1, 0, 2)), 0);
+        assert!(!grid.is_solid(Vec3i::new(-1, 0, 6)));
+    }
+
+    #[test]
+    fn contains_boxes_inside_the_grid() {
+        let grid = grid();
+        assert!(grid.contains(grid.origin, grid.size));
+        assert!(grid.contains(Vec3i::new(0, 1, 3), Vec3u::new(1, 2, 3)));
+        assert!(!grid.contains(Vec3i::new(0, 1, 3), Vec3u::new(2, 1, 1)));
+        assert!(!grid.contains(Vec3i::new(-2, 0, 2), Vec3u::new(1, 1, 1)));
+        assert!(!grid.contains(Vec3i::new(0, 0, 2), Vec3u::new(1, u32::MAX, 1)));
+    }
+
+    #[test]
+    fn probed_voxels_are_reused_for_a_while() {
+        let grid = grid();
+        let (origin, size) = (Vec3i::new(0, 1, 3), Vec3u::new(1, 1, 1));
+        assert!(reusable(&grid, 1.0, origin, size, 1.0));
+        assert!(reusable(
+            &grid,
+            1.0,
+            origin,
+            size,
+            1.0 + MAX_PROBE_AGE * 0.5
+        ));
+        // Many fixed updates in a row at the normal time scale.
+        let time = (0..5).fold(1.0, |time, _| time + 1.0 / 60.0);
+        assert!(reusable(&grid, 1.0, origin, size, time));
+
+        assert!(!reusable(
+            &grid,
+            1.0,
+            origin,
+            size,
+            1.0 + MAX_PROBE_AGE * 1.5
+        ));
+        assert!(!reusable(
+            &grid,
+            1.0,
+            origin,
+            size,
+            1.0 - MAX_PROBE_AGE * 1.5
+        ));
+        assert!(!reusable(&grid, 1.0, Vec3i::new(1, 1, 3), size, 1.0));
+    }
+}
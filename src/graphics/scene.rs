@@ -0,0 +1,107 @@
+use std::path::{Component, Path, PathBuf};
+
+use super::{shader_files::ShaderFiles, wgsl::Defines};
+
+/// Edge length of a voxel in world units, passed to the shaders as the `VOXEL_SIZE` define.
+pub const VOXEL_SIZE: f32 = 0.1;
+
+/// WGSL module defining the voxels traced by the voxel pass, imported by
+/// `wgsl/voxel/traversal.wgsl`.
+///
+/// It must provide the following functions, see `wgsl/scenes/demo.wgsl` for an example:
+/// - `fn scene_material(voxel_pos: vec3f, time: f32) -> u32`, the material of the voxel at
+///   `voxel_pos` or `MATERIAL_NONE` when it is empty.
+/// - `fn scene_material_color(material: u32) -> vec3f`, used by the materials debug view.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Scene {
+    /// A file of the shader files, relative to their root.
+    File(PathBuf),
+    /// WGSL source added to the shader files as `scenes/<name>.wgsl`.
+    Source { name: String, source: String },
+}
+
+impl Scene {
+    /// The animated sphere and torus.
+    pub fn demo() -> Self {
+        Scene::File("scenes/demo.wgsl".into())
+    }
+
+    pub fn source(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Scene::Source {
+            name: name.into(),
+            source: source.into(),
+        }
+    }
+
+    /// Path of the scene in the shader files.
+    pub fn path(&self) -> PathBuf {
+        match self {
+            Scene::File(path) => path.clone(),
+            Scene::Source { name, .. } => Path::new("scenes").join(format!("{name}.wgsl")),
+        }
+    }
+
+    /// Adds the scene source to `files` when it isn't a file already.
+    pub fn register(&self, files: ShaderFiles) -> ShaderFiles {
+        match self {
+            Scene::File(_) => files,
+            Scene::Source { source, .. } => files.with_source(self.path(), source.clone()),
+        }
+    }
+
+    /// Defines selecting the scene and its voxel size in the voxel shaders. The import path is
+    /// `/`-separated on every platform, so the preprocessed source and the pipeline cache keys
+    /// don't depend on it.
+    pub fn defines(&self) -> Defines {
+        let mut import = String::new();
+        for component in self.path().with_extension("").components() {
+            match component {
+                Component::Prefix(prefix) => import += &prefix.as_os_str().to_string_lossy(),
+                Component::RootDir => import.push('/'),
+                component => {
+                    if !import.is_empty() && !import.ends_with('/') {
+                        import.push('/');
+                    }
+                    import += &component.as_os_str().to_string_lossy();
+                }
+            }
+        }
+        Defines::from([
+            ("SCENE".to_owned(), import),
+            // Debug formatting keeps the decimal point of whole numbers, making a float literal.
+            ("VOXEL_SIZE".to_owned(), format!("{VOXEL_SIZE:?}")),
+        ])
+    }
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::demo()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scene_define(scene: Scene) -> String {
+        scene.defines()["SCENE"].clone()
+    }
+
+    #[test]
+    fn scene_import_is_slash_separated() {
+        assert_eq!(scene_define(Scene::demo()), "scenes/demo");
+        assert_eq!(scene_define(Scene::source("cave", "")), "scenes/cave");
+        let nested: PathBuf = ["scenes", "caves", "deep.wgsl"].iter().collect();
+        assert_eq!(scene_define(Scene::File(nested)), "scenes/caves/deep");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn absolute_scene_import_keeps_its_root() {
+        assert_eq!(
+            scene_define(Scene::File("/tmp/scenes/cave.wgsl".into())),
+            "/tmp/scenes/cave"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use wgpu::TextureFormat;
+
+use super::ctx::Image;
+
+/// What a screenshot captures.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ScreenshotSource {
+    /// The final frame, after post processing.
+    #[default]
+    Frame,
+    /// The raw color output of the voxel pass.
+    VoxelOutput,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ScreenshotOptions {
+    pub source: ScreenshotSource,
+    /// Multiplier applied to the window size, capped by the device texture size limit.
+    pub scale: u32,
+}
+
+impl Default for ScreenshotOptions {
+    fn default() -> Self {
+        Self {
+            source: ScreenshotSource::Frame,
+            scale: 1,
+        }
+    }
+}
+
+impl Image {
+    pub fn save_png(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let data = match self.format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => self.data.clone(),
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => self
+                .data
+                .chunks(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect(),
+            format => {
+                return Err(io::Error::other(format!(
+                    "Cannot save images of format {format:?} as png"
+                )))
+            }
+        };
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        if self.format.is_srgb() {
+            encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        }
+
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&data).map_err(io::Error::other)?;
+        writer.finish().map_err(io::Error::other)
+    }
+}
+
+/// Path of a new screenshot in `dir`, named after the current time in milliseconds.
+pub fn screenshot_path(dir: impl AsRef<Path>) -> PathBuf {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+
+    dir.as_ref().join(format!("screenshot_{timestamp}.png"))
+}
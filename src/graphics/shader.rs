@@ -0,0 +1,200 @@
+use std::{
+    borrow::Cow,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use wgpu::*;
+
+use super::{
+    ctx::GraphicsCtx,
+    layout::{check_layout, GpuStruct},
+    wgsl::{Defines, PreprocessError, PreprocessedWgsl, SourceLocation, WgslPreprocessor},
+};
+
+/// A preprocessed and validated shader module.
+pub struct Shader {
+    pub module: ShaderModule,
+    pub path: PathBuf,
+    pub wgsl: PreprocessedWgsl,
+    /// Naga representation of the shader, used for reflection.
+    pub naga: naga::Module,
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Preprocess(PreprocessError),
+    /// The preprocessed shader failed to parse or validate.
+    Compile {
+        path: PathBuf,
+        message: String,
+        /// Failing statement in the original files, when known.
+        location: Option<SourceLocation>,
+    },
+    /// Wgpu rejected the shader or a pipeline using it, like when bindings don't match.
+    Pipeline {
+        path: PathBuf,
+        message: String,
+    },
+    /// A rust struct doesn't have the same layout as its WGSL counterpart.
+    Layout {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl Shader {
+    /// Loads a shader from the shader files of the context, `path` being relative to their root.
+    pub fn load(
+        ctx: &GraphicsCtx,
+        path: impl AsRef<Path>,
+        defines: &Defines,
+    ) -> Result<Self, ShaderError> {
+        let wgsl = Self::preprocess(ctx, &path, defines)?;
+        Self::compile(ctx, path, wgsl)
+    }
+
+    pub fn preprocess(
+        ctx: &GraphicsCtx,
+        path: impl AsRef<Path>,
+        defines: &Defines,
+    ) -> Result<PreprocessedWgsl, ShaderError> {
+        WgslPreprocessor::new()
+            .with_files(ctx.shaders.clone())
+            .with_defines(defines.clone())
+            .load(path.as_ref())
+            .map_err(ShaderError::Preprocess)
+    }
+
+    /// Creates the module of a preprocessed shader.
+    ///
+    /// The shader is parsed once by naga and handed to wgpu, which validates it. Naga only
+    /// validates it again when wgpu rejects it, to map the error back to the original files.
+    pub fn compile(
+        ctx: &GraphicsCtx,
+        path: impl AsRef<Path>,
+        wgsl: PreprocessedWgsl,
+    ) -> Result<Self, ShaderError> {
+        let path = path.as_ref().to_owned();
+        let naga = parse(&path, &wgsl)?;
+
+        let module = validation_scope(ctx, &path, || {
+            ctx.device
+                .create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: path.to_str(),
+                    source: wgpu::ShaderSource::Naga(Cow::Owned(naga.clone())),
+                })
+        })
+        .map_err(|e| validate(&path, &wgsl, &naga).err().unwrap_or(e))?;
+
+        Ok(Self {
+            module,
+            path,
+            wgsl,
+            naga,
+        })
+    }
+
+    /// Checks that a struct uploaded to the GPU matches the WGSL struct of the same name.
+    pub fn check_layout<T: GpuStruct>(&self) -> Result<(), ShaderError> {
+        check_layout::<T>(&self.naga).map_err(|message| ShaderError::Layout {
+            path: self.path.clone(),
+            message,
+        })
+    }
+
+    /// Runs pipeline creation, turning wgpu validation errors into a [`ShaderError`]
+    /// instead of panicking.
+    pub fn create_pipeline<T>(
+        &self,
+        ctx: &GraphicsCtx,
+        create: impl FnOnce(&ShaderModule) -> T,
+    ) -> Result<T, ShaderError> {
+        validation_scope(ctx, &self.path, || create(&self.module))
+    }
+}
+
+fn validation_scope<T>(
+    ctx: &GraphicsCtx,
+    path: &Path,
+    f: impl FnOnce() -> T,
+) -> Result<T, ShaderError> {
+    ctx.device.push_error_scope(ErrorFilter::Validation);
+    let result = f();
+    match pollster::block_on(ctx.device.pop_error_scope()) {
+        Some(error) => Err(ShaderError::Pipeline {
+            path: path.to_owned(),
+            message: error.to_string(),
+        }),
+        None => Ok(result),
+    }
+}
+
+/// Parses the shader with naga so errors can be mapped back to the original files.
+fn parse(path: &Path, wgsl: &PreprocessedWgsl) -> Result<naga::Module, ShaderError> {
+    naga::front::wgsl::parse_str(&wgsl.source)
+        .map_err(|e| compile_error(path, wgsl, e.message().to_owned(), e.location(&wgsl.source)))
+}
+
+/// Validates the shader with naga, whose errors can be mapped back to the original files unlike
+/// those of wgpu.
+fn validate(
+    path: &Path,
+    wgsl: &PreprocessedWgsl,
+    module: &naga::Module,
+) -> Result<(), ShaderError> {
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(module)
+    .map_err(|e| {
+        let mut message = e.as_inner().to_string();
+        let mut source: &dyn std::error::Error = e.as_inner();
+        while let Some(next) = source.source() {
+            message += &format!(": {next}");
+            source = next;
+        }
+        compile_error(path, wgsl, message, e.location(&wgsl.source))
+    })?;
+
+    Ok(())
+}
+
+fn compile_error(
+    path: &Path,
+    wgsl: &PreprocessedWgsl,
+    message: String,
+    location: Option<naga::SourceLocation>,
+) -> ShaderError {
+    ShaderError::Compile {
+        path: path.to_owned(),
+        message,
+        location: location
+            .and_then(|location| wgsl.original_location(location.line_number as usize)),
+    }
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Preprocess(e) => write!(f, "Wgsl Preprocessor failed: {e}"),
+            ShaderError::Compile {
+                location: Some(location),
+                message,
+                ..
+            } => write!(f, "{location}: {message}"),
+            ShaderError::Compile {
+                path,
+                message,
+                location: None,
+            } => write!(f, "{}: {message}", path.display()),
+            ShaderError::Pipeline { path, message } => {
+                write!(f, "{}: pipeline creation failed: {message}", path.display())
+            }
+            ShaderError::Layout { path, message } => write!(f, "{}: {message}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
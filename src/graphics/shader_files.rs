@@ -0,0 +1,111 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use include_dir::{include_dir, Dir};
+
+static EMBEDDED_SHADERS: Dir = include_dir!("$CARGO_MANIFEST_DIR/wgsl");
+
+/// Environment variable pointing to a directory overriding the embedded shaders.
+pub const SHADER_DIR_ENV: &str = "VOXEL_RENDERER_SHADER_DIR";
+
+/// Virtual filesystem the shaders are loaded from.
+///
+/// Paths are relative to the shader root, like `voxel/main.wgsl`. Files are read from the sources
+/// added with [`ShaderFiles::with_source`] first, then from the override directory when they exist
+/// there, and from the shaders embedded in the binary otherwise.
+#[derive(Clone, Debug)]
+pub struct ShaderFiles {
+    sources: Arc<BTreeMap<PathBuf, String>>,
+    override_dir: Option<PathBuf>,
+    embedded: Option<&'static Dir<'static>>,
+}
+
+impl ShaderFiles {
+    /// Shaders embedded at compile time.
+    pub fn embedded() -> Self {
+        Self {
+            sources: Default::default(),
+            override_dir: None,
+            embedded: Some(&EMBEDDED_SHADERS),
+        }
+    }
+
+    /// Shaders read from `dir` only.
+    pub fn disk(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            sources: Default::default(),
+            override_dir: Some(dir.into()),
+            embedded: None,
+        }
+    }
+
+    /// Embedded shaders overridden by the directory in [`SHADER_DIR_ENV`] if set.
+    /// Debug builds default to the `wgsl` directory of the crate so shaders can be edited live.
+    pub fn from_env() -> Self {
+        let dev_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("wgsl");
+        let override_dir = std::env::var_os(SHADER_DIR_ENV)
+            .map(PathBuf::from)
+            .or_else(|| (cfg!(debug_assertions) && dev_dir.is_dir()).then_some(dev_dir));
+
+        Self {
+            override_dir,
+            ..Self::embedded()
+        }
+    }
+
+    pub fn with_override_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.override_dir = dir;
+        self
+    }
+
+    /// Adds a file that only exists in memory, taking precedence over the others.
+    pub fn with_source(mut self, path: impl Into<PathBuf>, source: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.sources).insert(path.into(), source.into());
+        self
+    }
+
+    pub fn override_dir(&self) -> Option<&Path> {
+        self.override_dir.as_deref()
+    }
+
+    pub fn read(&self, path: &Path) -> io::Result<String> {
+        if let Some(source) = self.sources.get(path) {
+            return Ok(source.clone());
+        }
+        if let Some(disk_path) = self.disk_path(path) {
+            return std::fs::read_to_string(disk_path);
+        }
+
+        self.embedded
+            .and_then(|dir| dir.get_file(path))
+            .and_then(|file| file.contents_utf8())
+            .map(str::to_owned)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such shader file"))
+    }
+
+    pub fn exists(&self, path: &Path) -> bool {
+        self.sources.contains_key(path)
+            || self.disk_path(path).is_some()
+            || self
+                .embedded
+                .is_some_and(|dir| dir.get_file(path).is_some())
+    }
+
+    /// Location on disk of a file when it is overridden.
+    pub fn disk_path(&self, path: &Path) -> Option<PathBuf> {
+        self.override_dir
+            .as_ref()
+            .map(|dir| dir.join(path))
+            .filter(|path| path.is_file())
+    }
+}
+
+impl Default for ShaderFiles {
+    fn default() -> Self {
+        Self::embedded()
+    }
+}
@@ -0,0 +1,287 @@
+use cgmath::InnerSpace;
+
+use super::{
+    camera::Camera,
+    probe::{voxel_at, VoxelWorld},
+    scene::VOXEL_SIZE,
+};
+use crate::maths::{Vec3f, Vec3i, Vec3u};
+
+/// Width and depth of the body in world units.
+const BODY_WIDTH: f32 = 0.6;
+const BODY_HEIGHT: f32 = 1.8;
+/// Height of the camera above the feet.
+const EYE_HEIGHT: f32 = 1.6;
+/// Downward acceleration in world units per second squared.
+const GRAVITY: f32 = 20.0;
+const JUMP_SPEED: f32 = 6.0;
+const MAX_FALL_SPEED: f32 = 50.0;
+/// Horizontal speed cap, the controller speed can be raised far beyond a walking pace.
+const MAX_WALK_SPEED: f32 = 10.0;
+/// Ledges up to this height are climbed without jumping.
+const STEP_HEIGHT: f32 = VOXEL_SIZE;
+/// Longest time step simulated at once, so a slow frame can't move the body through walls or
+/// outside of the probed voxels.
+const MAX_DT: f32 = 0.05;
+/// Gap kept between the body and the voxels it rests against.
+const SKIN: f32 = 1e-4;
+
+/// Axis aligned box walking the camera through the voxels, with gravity and collisions.
+pub struct Body {
+    /// Center of the bottom face of the box.
+    pub feet: Vec3f,
+    pub velocity: Vec3f,
+    pub on_ground: bool,
+}
+
+impl Body {
+    /// Body standing below the camera.
+    pub fn new(camera: &Camera) -> Self {
+        Self {
+            feet: camera.position - Vec3f::new(0.0, EYE_HEIGHT, 0.0),
+            velocity: Vec3f::new(0.0, 0.0, 0.0),
+            on_ground: false,
+        }
+    }
+
+    /// Voxels the body can touch during the next `dt` seconds of updates, as the origin and size
+    /// of the box to pass to [`VoxelProbe::sample`](super::probe::VoxelProbe::sample).
+    pub fn reach(&self, camera: &Camera, dt: f32) -> (Vec3i, Vec3u) {
+        let horizontal = camera.controller.speed.min(MAX_WALK_SPEED) * dt;
+        let vertical = (self.velocity.y.abs().max(JUMP_SPEED) + GRAVITY * dt) * dt + STEP_HEIGHT;
+        let margin =
+            Vec3f::new(horizontal, vertical, horizontal) + Vec3f::new(1.0, 1.0, 1.0) * VOXEL_SIZE;
+
+        let (min, max) = self.bounds();
+        let min = voxel_at(min - margin);
+        let max = voxel_at(max + margin);
+        let size = (max - min).map(|extent| extent as u32 + 1);
+        (min, size)
+    }
+
+    /// Moves the body from the controller of `camera`, then puts the camera at eye height.
+    pub fn update(&mut self, camera: &mut Camera, world: &impl VoxelWorld, dt: f32) {
+        let dt = dt.min(MAX_DT);
+        let controller = &camera.controller;
+
+        // Walking follows the yaw only, looking up or down doesn't slow it down.
+        let forward = Vec3f::new(-camera.yaw.sin(), 0.0, -camera.yaw.cos());
+        let right = Vec3f::new(camera.yaw.cos(), 0.0, -camera.yaw.sin());
+        let pad = controller.gamepad.with_deadzone();
+        let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+        let mut wish = forward * (axis(controller.forward, controller.backward) + pad.left_stick.y)
+            + right * (axis(controller.right, controller.left) + pad.left_stick.x);
+        if wish.magnitude2() > 1.0 {
+            wish = wish.normalize();
+        }
+
+        let speed = controller.speed.min(MAX_WALK_SPEED);
+        self.velocity.x = wish.x * speed;
+        self.velocity.z = wish.z * speed;
+        if self.on_ground && (controller.up || pad.right_trigger > 0.5) {
+            self.velocity.y = JUMP_SPEED;
+        }
+        self.velocity.y = (self.velocity.y - GRAVITY * dt).max(-MAX_FALL_SPEED);
+
+        self.move_by(world, self.velocity * dt);
+
+        camera.position = self.feet + Vec3f::new(0.0, EYE_HEIGHT, 0.0);
+        camera.update_controls(dt);
+    }
+
+    /// Moves in steps shorter than a voxel so thin walls are never skipped, the vertical axis
+    /// first so the body knows whether it stands on the ground before walking.
+    fn move_by(&mut self, world: &impl VoxelWorld, delta: Vec3f) {
+        let longest = delta.x.abs().max(delta.y.abs()).max(delta.z.abs());
+        let steps = (longest / (VOXEL_SIZE * 0.5)).ceil().max(1.0) as u32;
+        let step = delta / steps as f32;
+
+        self.on_ground = false;
+        for _ in 0..steps {
+            for axis in [1, 0, 2] {
+                self.move_axis(world, axis, step[axis]);
+            }
+        }
+    }
+
+    /// Moves along `axis`, stepping up low ledges and sliding against the voxels blocking the
+    /// way.
+    fn move_axis(&mut self, world: &impl VoxelWorld, axis: usize, amount: f32) {
+        if amount == 0.0 {
+            return;
+        }
+
+        self.feet[axis] += amount;
+        if !self.collides(world) {
+            return;
+        }
+
+        if axis != 1 && self.on_ground {
+            self.feet.y += STEP_HEIGHT;
+            if !self.collides(world) {
+                return;
+            }
+            self.feet.y -= STEP_HEIGHT;
+        }
+
+        // Steps are shorter than a voxel, so the box only entered the voxels on its leading face.
+        let (min, max) = self.bounds();
+        if amount > 0.0 {
+            let boundary = (max[axis] / VOXEL_SIZE).floor() * VOXEL_SIZE;
+            self.feet[axis] += boundary - SKIN - max[axis];
+        } else {
+            let boundary = ((min[axis] / VOXEL_SIZE).floor() + 1.0) * VOXEL_SIZE;
+            self.feet[axis] += boundary + SKIN - min[axis];
+            self.on_ground |= axis == 1;
+        }
+        self.velocity[axis] = 0.0;
+    }
+
+    fn collides(&self, world: &impl VoxelWorld) -> bool {
+        let (min, max) = self.bounds();
+        let (min, max) = (voxel_at(min), voxel_at(max));
+        (min.x..=max.x).any(|x| {
+            (min.y..=max.y).any(|y| (min.z..=max.z).any(|z| world.is_solid(Vec3i::new(x, y, z))))
+        })
+    }
+
+    fn bounds(&self) -> (Vec3f, Vec3f) {
+        let half = BODY_WIDTH / 2.0;
+        (
+            self.feet - Vec3f::new(half, 0.0, half),
+            self.feet + Vec3f::new(half, BODY_HEIGHT, half),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// Voxels for which the function returns `true` are solid.
+    struct World(fn(Vec3i) -> bool);
+
+    impl VoxelWorld for World {
+        fn is_solid(&self, voxel: Vec3i) -> bool {
+            (self.0)(voxel)
+        }
+    }
+
+    const FLOOR: World = World(|voxel| voxel.y < 0);
+
+    /// Body with its feet at `feet` and the camera above them, looking towards -z.
+    fn body_at(feet: Vec3f) -> (Camera, Body) {
+        let camera = Camera {
+            position: feet + Vec3f::new(0.0, EYE_HEIGHT, 0.0),
+            ..Default::default()
+        };
+        let body = Body::new(&camera);
+        (camera, body)
+    }
+
+    fn run(camera: &mut Camera, body: &mut Body, world: &World, seconds: f32, dt: f32) {
+        for _ in 0..(seconds / dt).round() as u32 {
+            body.update(camera, world, dt);
+        }
+    }
+
+    #[test]
+    fn falls_and_lands_on_the_floor() {
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 1.0, 0.0));
+        run(&mut camera, &mut body, &FLOOR, 2.0, DT);
+
+        assert!(body.on_ground);
+        assert!(body.feet.y.abs() < 1e-3);
+        assert_eq!(body.velocity.y, 0.0);
+        assert_eq!(
+            camera.position,
+            body.feet + Vec3f::new(0.0, EYE_HEIGHT, 0.0)
+        );
+    }
+
+    #[test]
+    fn walks_at_the_controller_speed_up_to_a_limit() {
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 0.0, 0.0));
+        camera.controller.forward = true;
+        run(&mut camera, &mut body, &FLOOR, 1.0, DT);
+        assert!((-body.feet.z - camera.controller.speed).abs() < 0.05);
+
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 0.0, 0.0));
+        camera.controller.forward = true;
+        camera.controller.speed = 100.0;
+        run(&mut camera, &mut body, &FLOOR, 1.0, DT);
+        assert!((-body.feet.z - MAX_WALK_SPEED).abs() < 0.05);
+    }
+
+    #[test]
+    fn jumps_only_from_the_ground() {
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 0.0, 0.0));
+        camera.controller.up = true;
+        run(&mut camera, &mut body, &FLOOR, 0.2, DT);
+        assert!(!body.on_ground);
+        assert!(body.feet.y > 0.5);
+
+        // Holding jump in the air doesn't climb any higher.
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 5.0, 0.0));
+        camera.controller.up = true;
+        run(&mut camera, &mut body, &FLOOR, 0.1, DT);
+        assert!(body.feet.y < 5.0);
+    }
+
+    #[test]
+    fn slides_along_a_wall() {
+        // Wall on the right, starting at x = 0.5.
+        let world = World(|voxel| voxel.y < 0 || voxel.x >= 5);
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 0.0, 0.0));
+        camera.controller.forward = true;
+        camera.controller.right = true;
+        run(&mut camera, &mut body, &world, 1.0, DT);
+
+        let (_, max) = body.bounds();
+        assert!(max.x <= 0.5);
+        assert!(max.x > 0.49);
+        assert!(body.feet.z < -1.0);
+        assert!(body.feet.y.abs() < 1e-3);
+    }
+
+    #[test]
+    fn steps_up_a_one_voxel_ledge() {
+        let world = World(|voxel| voxel.y < 0 || (voxel.y == 0 && voxel.z < -5));
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 0.0, 0.0));
+        camera.controller.forward = true;
+        run(&mut camera, &mut body, &world, 1.0, DT);
+
+        assert!(body.feet.z < -1.0);
+        assert!((body.feet.y - VOXEL_SIZE).abs() < 1e-3);
+        assert!(body.on_ground);
+    }
+
+    #[test]
+    fn stops_at_a_two_voxel_ledge() {
+        let world = World(|voxel| voxel.y < 0 || (voxel.y <= 1 && voxel.z < -5));
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 0.0, 0.0));
+        camera.controller.forward = true;
+        run(&mut camera, &mut body, &world, 1.0, DT);
+
+        let (min, _) = body.bounds();
+        assert!(min.z >= -0.5);
+        assert!(min.z < -0.49);
+        assert!(body.feet.y.abs() < 1e-3);
+    }
+
+    #[test]
+    fn does_not_pass_through_a_thin_wall_at_high_speed() {
+        // One voxel thick wall spanning z = -1 to -0.9.
+        let world = World(|voxel| voxel.y < 0 || voxel.z == -10);
+        let (mut camera, mut body) = body_at(Vec3f::new(0.0, 0.0, 0.0));
+        camera.controller.forward = true;
+        camera.controller.speed = 100.0;
+        // Every update moves further than the wall is thick.
+        run(&mut camera, &mut body, &world, 1.0, MAX_DT);
+
+        let (min, _) = body.bounds();
+        assert!(min.z >= -0.9);
+    }
+}
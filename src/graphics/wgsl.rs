@@ -1,21 +1,476 @@
-use std::path::Path;
-
-pub fn load_wgsl_with_preprocessor(path: impl AsRef<Path>) -> String {
-    std::fs::read_to_string(path.as_ref())
-        .unwrap()
-        .lines()
-        .map(|line| {
-            (if line.starts_with("#") {
-                if let Some(import_path) = line.strip_prefix("#import ") {
-                    let current_dir = path.as_ref().parent().unwrap();
-                    let new_path = current_dir.join(import_path.to_owned() + ".wgsl");
-                    load_wgsl_with_preprocessor(new_path)
-                } else {
-                    panic!("Wgsl Preprocessor failed to parse: {}", line);
-                }
-            } else {
-                line.to_owned()
-            }) + "\n"
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    path::{Component, Path, PathBuf},
+};
+
+use super::shader_files::ShaderFiles;
+
+/// Names defined before preprocessing, mapped to the value they are substituted with.
+pub type Defines = BTreeMap<String, String>;
+
+/// Output of the preprocessor, remembering where each line comes from.
+pub struct PreprocessedWgsl {
+    pub source: String,
+    files: Vec<PathBuf>,
+    /// File index and line number of each line of `source`.
+    lines: Vec<(usize, usize)>,
+}
+
+/// Line of an original file, before preprocessing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub path: PathBuf,
+    pub line: usize,
+}
+
+impl PreprocessedWgsl {
+    /// Original location of a line of the preprocessed source, both starting at 1.
+    pub fn original_location(&self, line: usize) -> Option<SourceLocation> {
+        let &(file, line) = self.lines.get(line.checked_sub(1)?)?;
+        Some(SourceLocation {
+            path: self.files[file].clone(),
+            line,
         })
-        .collect()
+    }
+
+    /// Every file that was included, starting with the entry point.
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.line)
+    }
+}
+
+/// Wgsl preprocessor supporting the following directives:
+/// - `#import name` inlines `name.wgsl`, resolved relative to the importing file first, then
+///   to each search root and finally to the root of the shader files. Each file is included at
+///   most once per compilation. `name` can be a define, like `#import SCENE`.
+/// - `#define NAME [value]` defines `NAME`, which is then replaced by `value` when not empty.
+/// - `#ifdef NAME`, `#ifndef NAME`, `#else` and `#endif` include lines depending on `NAME`
+///   being defined. They can be nested but must be balanced within a file.
+#[derive(Default, Clone)]
+pub struct WgslPreprocessor {
+    files: ShaderFiles,
+    search_roots: Vec<PathBuf>,
+    defines: Defines,
+}
+
+#[derive(Debug)]
+pub enum PreprocessError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    ImportNotFound {
+        import: String,
+        from: PathBuf,
+    },
+    /// Files importing each other, the first file is repeated at the end.
+    ImportCycle(Vec<PathBuf>),
+    UnknownDirective {
+        path: PathBuf,
+        line: usize,
+        directive: String,
+    },
+    /// `#else` or `#endif` without a matching `#ifdef`, or an `#ifdef` never closed.
+    UnbalancedConditional {
+        path: PathBuf,
+        line: usize,
+    },
+}
+
+struct Compilation {
+    included: HashSet<PathBuf>,
+    stack: Vec<PathBuf>,
+    defines: Defines,
+    output: PreprocessedWgsl,
+}
+
+struct Conditional {
+    line: usize,
+    /// Whether the lines of the current branch are included.
+    active: bool,
+    /// Whether the enclosing block is included.
+    parent_active: bool,
+    in_else: bool,
+}
+
+impl WgslPreprocessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_files(mut self, files: ShaderFiles) -> Self {
+        self.files = files;
+        self
+    }
+
+    pub fn with_search_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.search_roots.push(root.into());
+        self
+    }
+
+    pub fn with_define(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.defines.insert(name.into(), value.into());
+        self
+    }
+
+    pub fn with_defines(mut self, defines: Defines) -> Self {
+        self.defines.extend(defines);
+        self
+    }
+
+    pub fn load(&self, path: impl AsRef<Path>) -> Result<PreprocessedWgsl, PreprocessError> {
+        let mut compilation = Compilation {
+            included: HashSet::new(),
+            stack: Vec::new(),
+            defines: self.defines.clone(),
+            output: PreprocessedWgsl {
+                source: String::new(),
+                files: Vec::new(),
+                lines: Vec::new(),
+            },
+        };
+        self.include(normalize(path.as_ref()), &mut compilation)?;
+
+        Ok(compilation.output)
+    }
+
+    fn include(&self, path: PathBuf, compilation: &mut Compilation) -> Result<(), PreprocessError> {
+        if let Some(start) = compilation.stack.iter().position(|p| *p == path) {
+            let mut cycle = compilation.stack[start..].to_vec();
+            cycle.push(path);
+            return Err(PreprocessError::ImportCycle(cycle));
+        }
+        if !compilation.included.insert(path.clone()) {
+            return Ok(());
+        }
+
+        let source = self
+            .files
+            .read(&path)
+            .map_err(|error| PreprocessError::Io {
+                path: path.clone(),
+                error,
+            })?;
+
+        let file = compilation.output.files.len();
+        compilation.output.files.push(path.clone());
+
+        compilation.stack.push(path.clone());
+        let mut conditionals: Vec<Conditional> = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let active = conditionals.last().is_none_or(|c| c.active);
+
+            if !line.starts_with("#") {
+                if active {
+                    let output = &mut compilation.output;
+                    output.source += &substitute(line, &compilation.defines);
+                    output.source += "\n";
+                    output.lines.push((file, line_number));
+                }
+                continue;
+            }
+
+            let unknown_directive = || PreprocessError::UnknownDirective {
+                path: path.clone(),
+                line: line_number,
+                directive: line.to_owned(),
+            };
+            let unbalanced = || PreprocessError::UnbalancedConditional {
+                path: path.clone(),
+                line: line_number,
+            };
+
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("#ifdef" | "#ifndef") => {
+                    let name = words.next().ok_or_else(unknown_directive)?;
+                    let defined = compilation.defines.contains_key(name);
+                    conditionals.push(Conditional {
+                        line: line_number,
+                        active: active && (defined == line.starts_with("#ifdef")),
+                        parent_active: active,
+                        in_else: false,
+                    });
+                }
+                Some("#else") => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .filter(|c| !c.in_else)
+                        .ok_or_else(unbalanced)?;
+                    conditional.active = conditional.parent_active && !conditional.active;
+                    conditional.in_else = true;
+                }
+                Some("#endif") => {
+                    conditionals.pop().ok_or_else(unbalanced)?;
+                }
+                _ if !active => {}
+                Some("#define") => {
+                    let name = words.next().ok_or_else(unknown_directive)?;
+                    let value = words.collect::<Vec<_>>().join(" ");
+                    compilation.defines.insert(name.to_owned(), value);
+                }
+                Some("#import") => {
+                    let import = words.next().ok_or_else(unknown_directive)?;
+                    let import = substitute(import, &compilation.defines);
+                    let import_path = self.resolve(&import, &path)?;
+                    self.include(import_path, compilation)?;
+                }
+                _ => return Err(unknown_directive()),
+            }
+        }
+        if let Some(conditional) = conditionals.pop() {
+            return Err(PreprocessError::UnbalancedConditional {
+                path,
+                line: conditional.line,
+            });
+        }
+        compilation.stack.pop();
+
+        Ok(())
+    }
+
+    fn resolve(&self, import: &str, from: &Path) -> Result<PathBuf, PreprocessError> {
+        let file_name = import.to_owned() + ".wgsl";
+        let current_dir = from.parent().unwrap_or(Path::new(""));
+
+        std::iter::once(current_dir)
+            .chain(self.search_roots.iter().map(PathBuf::as_path))
+            .chain(std::iter::once(Path::new("")))
+            .map(|dir| normalize(&dir.join(&file_name)))
+            .find(|candidate| self.files.exists(candidate))
+            .ok_or_else(|| PreprocessError::ImportNotFound {
+                import: import.to_owned(),
+                from: from.to_owned(),
+            })
+    }
+}
+
+/// Replaces the identifiers of `line` that are defined with a non empty value.
+fn substitute(line: &str, defines: &Defines) -> String {
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        let (before, from_start) = rest.split_at(start);
+        let end = from_start
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(from_start.len());
+        let (identifier, after) = from_start.split_at(end);
+
+        output += before;
+        // Identifiers are never preceded by a digit, this avoids matching suffixes like `1u`.
+        let is_suffix = output.ends_with(|c: char| c.is_ascii_digit());
+        match defines.get(identifier) {
+            Some(value) if !value.is_empty() && !is_suffix => output += value,
+            _ => output += identifier,
+        }
+        rest = after;
+    }
+
+    output + rest
+}
+
+/// Lexically resolves `.` and `..` so the same file is always identified by the same path.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if normalized.file_name().is_some() => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreprocessError::Io { path, error } => {
+                write!(f, "could not read {}: {error}", path.display())
+            }
+            PreprocessError::ImportNotFound { import, from } => {
+                write!(
+                    f,
+                    "could not resolve #import {import} in {}",
+                    from.display()
+                )
+            }
+            PreprocessError::ImportCycle(cycle) => {
+                let cycle: Vec<_> = cycle.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "import cycle: {}", cycle.join(" -> "))
+            }
+            PreprocessError::UnknownDirective {
+                path,
+                line,
+                directive,
+            } => write!(
+                f,
+                "unknown directive at {}:{line}: {directive}",
+                path.display()
+            ),
+            PreprocessError::UnbalancedConditional { path, line } => {
+                write!(f, "unbalanced conditional at {}:{line}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for PreprocessError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn preprocessor(sources: &[(&str, &str)]) -> WgslPreprocessor {
+        let files = sources
+            .iter()
+            .fold(ShaderFiles::embedded(), |files, (path, source)| {
+                files.with_source(path, *source)
+            });
+        WgslPreprocessor::new().with_files(files)
+    }
+
+    #[test]
+    fn diamond_imports_are_included_once() {
+        let wgsl = preprocessor(&[
+            ("test/main.wgsl", "#import left\n#import right\nmain"),
+            ("test/left.wgsl", "#import shared\nleft"),
+            ("test/right.wgsl", "#import shared\nright"),
+            ("test/shared.wgsl", "shared"),
+        ])
+        .load("test/main.wgsl")
+        .unwrap();
+
+        assert_eq!(wgsl.source, "shared\nleft\nright\nmain\n");
+        assert_eq!(
+            wgsl.original_location(3),
+            Some(SourceLocation {
+                path: "test/right.wgsl".into(),
+                line: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn import_cycles_are_reported() {
+        let error = preprocessor(&[
+            ("test/a.wgsl", "#import b"),
+            ("test/b.wgsl", "#import c"),
+            ("test/c.wgsl", "#import ./b"),
+        ])
+        .load("test/a.wgsl")
+        .err()
+        .unwrap();
+
+        let PreprocessError::ImportCycle(cycle) = error else {
+            panic!("expected an import cycle, got {error}");
+        };
+        let expected: Vec<PathBuf> = ["test/b.wgsl", "test/c.wgsl", "test/b.wgsl"]
+            .map(PathBuf::from)
+            .to_vec();
+        assert_eq!(cycle, expected);
+    }
+
+    #[test]
+    fn missing_imports_are_reported() {
+        let error = preprocessor(&[("test/main.wgsl", "#import missing")])
+            .load("test/main.wgsl")
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(
+                &error,
+                PreprocessError::ImportNotFound { import, from }
+                    if import == "missing" && from == Path::new("test/main.wgsl")
+            ),
+            "unexpected error {error}"
+        );
+    }
+
+    #[test]
+    fn imports_resolve_from_search_roots_after_the_importing_file() {
+        let wgsl = preprocessor(&[
+            ("test/main.wgsl", "#import a\n#import b"),
+            ("test/a.wgsl", "local a"),
+            ("lib/a.wgsl", "root a"),
+            ("lib/b.wgsl", "root b"),
+        ])
+        .with_search_root("lib")
+        .load("test/main.wgsl")
+        .unwrap();
+
+        assert_eq!(wgsl.source, "local a\nroot b\n");
+    }
+
+    #[test]
+    fn nested_conditionals_follow_the_innermost_branch() {
+        let source = "#ifdef A\n#ifndef B\na not b\n#else\na and b\n#endif\n#else\nnot a\n#ifdef B\nnot a and b\n#endif\n#endif";
+        let load = |defines: &[&str]| {
+            defines
+                .iter()
+                .fold(preprocessor(&[("test/main.wgsl", source)]), |p, name| {
+                    p.with_define(*name, "")
+                })
+                .load("test/main.wgsl")
+                .unwrap()
+                .source
+        };
+
+        assert_eq!(load(&["A"]), "a not b\n");
+        assert_eq!(load(&["A", "B"]), "a and b\n");
+        assert_eq!(load(&["B"]), "not a\nnot a and b\n");
+        assert_eq!(load(&[]), "not a\n");
+    }
+
+    #[test]
+    fn unbalanced_conditionals_are_reported() {
+        for (source, expected_line) in [
+            ("line\n#ifdef A\nline", 2),
+            ("#endif", 1),
+            ("#ifdef A\n#else\n#else\n#endif", 3),
+        ] {
+            let error = preprocessor(&[("test/main.wgsl", source)])
+                .load("test/main.wgsl")
+                .err()
+                .unwrap();
+
+            assert!(
+                matches!(
+                    error,
+                    PreprocessError::UnbalancedConditional { line, .. } if line == expected_line
+                ),
+                "unexpected error for {source:?}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn defines_are_substituted_in_code_and_imports() {
+        let wgsl = preprocessor(&[
+            (
+                "test/main.wgsl",
+                "#ifndef SCENE\n#define SCENE scenes/missing\n#endif\n#import SCENE\nlet size = SIZE * 1u;",
+            ),
+            ("test/scenes/level.wgsl", "level"),
+        ])
+        .with_define("SCENE", "scenes/level")
+        .with_define("SIZE", "4")
+        .with_define("u", "not a suffix")
+        .load("test/main.wgsl")
+        .unwrap();
+
+        assert_eq!(wgsl.source, "level\nlet size = 4 * 1u;\n");
+    }
 }
@@ -0,0 +1,167 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use winit::keyboard::KeyCode;
+
+/// Where the input map is loaded from, relative to the working directory.
+pub const INPUT_CONFIG_PATH: &str = "input.toml";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ZoomIn,
+    ZoomOut,
+    NextProjection,
+    ToggleWalk,
+    ToggleOrbit,
+    RecordCameraPath,
+    PlayCameraPath,
+    Refresh,
+    NextDebugView,
+    PreviousDebugView,
+    Screenshot,
+    HighResScreenshot,
+    PauseTime,
+    StepTime,
+    SlowDownTime,
+    SpeedUpTime,
+    ReleaseCursor,
+}
+
+/// Keys bound to each action.
+///
+/// The config file lists the keys of the actions to rebind using winit key code names, actions
+/// missing from it keep their default bindings:
+/// ```toml
+/// move_forward = ["KeyZ", "ArrowUp"]
+/// move_left = ["KeyQ"]
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        Self {
+            bindings: BTreeMap::from([
+                (Action::MoveForward, vec![KeyCode::KeyW]),
+                (Action::MoveBackward, vec![KeyCode::KeyS]),
+                (Action::MoveLeft, vec![KeyCode::KeyA]),
+                (Action::MoveRight, vec![KeyCode::KeyD]),
+                (Action::MoveUp, vec![KeyCode::Space]),
+                (Action::MoveDown, vec![KeyCode::ShiftLeft]),
+                (Action::ZoomIn, vec![KeyCode::Equal, KeyCode::NumpadAdd]),
+                (
+                    Action::ZoomOut,
+                    vec![KeyCode::Minus, KeyCode::NumpadSubtract],
+                ),
+                (Action::NextProjection, vec![KeyCode::F3]),
+                (Action::ToggleWalk, vec![KeyCode::F4]),
+                (Action::ToggleOrbit, vec![KeyCode::F8]),
+                (Action::Refresh, vec![KeyCode::F5]),
+                (Action::RecordCameraPath, vec![KeyCode::F6]),
+                (Action::PlayCameraPath, vec![KeyCode::F7]),
+                (Action::NextDebugView, vec![KeyCode::F1]),
+                (Action::PreviousDebugView, vec![KeyCode::F2]),
+                (Action::Screenshot, vec![KeyCode::F12]),
+                (Action::HighResScreenshot, vec![KeyCode::F11]),
+                (Action::PauseTime, vec![KeyCode::F9]),
+                (Action::StepTime, vec![KeyCode::F10]),
+                (Action::SlowDownTime, vec![KeyCode::BracketLeft]),
+                (Action::SpeedUpTime, vec![KeyCode::BracketRight]),
+                (Action::ReleaseCursor, vec![KeyCode::Escape]),
+            ]),
+        }
+    }
+}
+
+impl InputMap {
+    /// Default bindings overridden by those of the config file at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::read_to_string(path)?;
+        let overrides: InputMap =
+            toml::from_str(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        let mut map = Self::default();
+        map.bindings.extend(overrides.bindings);
+        Ok(map)
+    }
+
+    /// Loads the map from [`INPUT_CONFIG_PATH`] if it exists, using the defaults otherwise.
+    pub fn from_config() -> Self {
+        match Self::load(INPUT_CONFIG_PATH) {
+            Ok(map) => map,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Self::default(),
+            Err(e) => {
+                eprintln!("Could not load {INPUT_CONFIG_PATH}, using default bindings: {e}");
+                Self::default()
+            }
+        }
+    }
+
+    pub fn bind(&mut self, action: Action, key: KeyCode) {
+        self.bindings.entry(action).or_default().push(key);
+    }
+
+    pub fn keys(&self, action: Action) -> &[KeyCode] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn actions(&self, key: KeyCode) -> impl Iterator<Item = Action> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, keys)| keys.contains(&key))
+            .map(|(action, _)| *action)
+    }
+}
+
+/// Tracks the held keys to turn key events into actions.
+#[derive(Default)]
+pub struct Input {
+    pub map: InputMap,
+    held: HashSet<KeyCode>,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Self {
+        Self {
+            map,
+            held: HashSet::new(),
+        }
+    }
+
+    /// Actions bound to `key` when it is pressed or released, nothing when its state didn't change.
+    pub fn key(&mut self, key: KeyCode, pressed: bool) -> Vec<Action> {
+        let changed = if pressed {
+            self.held.insert(key)
+        } else {
+            self.held.remove(&key)
+        };
+
+        if changed {
+            self.map.actions(key).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Whether any key bound to `action` is held.
+    pub fn is_held(&self, action: Action) -> bool {
+        self.map
+            .keys(action)
+            .iter()
+            .any(|key| self.held.contains(key))
+    }
+}
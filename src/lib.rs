@@ -1,31 +1,77 @@
 use std::{sync::Arc, time::Instant};
 
-use graphics::{camera::Camera, Graphics};
+use clock::{Clock, FIXED_DT};
+use gamepad::{system_gamepads, GamepadSource};
+use graphics::{
+    camera::Camera,
+    camera_path::{CameraPath, CameraPathPlayer},
+    orbit::Orbit,
+    screenshot::ScreenshotOptions,
+    walk::Body,
+    Graphics,
+};
+use input::{Action, Input, InputMap};
+use maths::{Vec2f, Vec3f};
 use winit::{
     application::ApplicationHandler,
-    event::{DeviceEvent, ElementState, MouseScrollDelta, RawKeyEvent, WindowEvent},
+    event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, RawKeyEvent, WindowEvent},
     event_loop::ActiveEventLoop,
     keyboard::{KeyCode, PhysicalKey},
-    window::{Window, WindowId},
+    window::{CursorGrabMode, Window, WindowId},
 };
 
-mod graphics;
-mod maths;
+pub mod clock;
+pub mod gamepad;
+pub mod graphics;
+pub mod input;
+pub mod maths;
+
+const SCREENSHOT_DIR: &str = "screenshots";
+const CAMERA_PATH_FILE: &str = "camera_path.toml";
+/// Resolution multiplier of high resolution screenshots, others are at the window resolution.
+const HIGH_RES_SCREENSHOT_SCALE: u32 = 2;
+/// Touchpads scroll in pixels, this many make a mouse wheel notch.
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
 
+#[allow(clippy::large_enum_variant)]
 #[derive(Default)]
 pub enum App {
     #[default]
     Init,
     Running {
-        start_time: Instant,
         last_update: Instant,
+        /// Scene time and fixed updates.
+        clock: Clock,
         window: Arc<Window>,
         graphics: Graphics<'static>,
+        /// Mouse movements rotate the camera while the cursor is grabbed.
+        cursor_grabbed: bool,
+        input: Input,
+        gamepad: Option<Box<dyn GamepadSource>>,
 
         camera: Camera,
+        /// Camera movement of the last update, undone by the fraction of an update not elapsed
+        /// yet when rendering.
+        last_motion: Vec3f,
+        /// Yaw and pitch change of the last update, interpolated like `last_motion`. Mouse look
+        /// happens between updates and is never delayed.
+        last_turn: Vec2f,
+        camera_path: CameraPathPlayer,
+        movement: Movement,
+        /// Mouse button held while orbiting.
+        dragging: Option<MouseButton>,
     },
 }
 
+/// How the controls move the camera.
+pub enum Movement {
+    Fly,
+    /// Walks with gravity and collisions.
+    Walk(Body),
+    /// Rotates around a focus point while dragging with the left button, pans with the others.
+    Orbit(Orbit),
+}
+
 impl ApplicationHandler for App {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let window = Arc::new(
@@ -33,14 +79,25 @@ impl ApplicationHandler for App {
                 .create_window(Window::default_attributes())
                 .unwrap(),
         );
-        let graphics = Graphics::new(window.inner_size(), window.clone());
+        let mut graphics = Graphics::new(window.inner_size(), window.clone());
+        if let Err(e) = graphics.watch_shaders() {
+            eprintln!("Shader hot reload disabled: {e}");
+        }
 
         *self = Self::Running {
-            start_time: Instant::now(),
             last_update: Instant::now(),
+            clock: Clock::new(),
             window,
             graphics,
+            cursor_grabbed: false,
+            input: Input::new(InputMap::from_config()),
+            gamepad: system_gamepads(),
             camera: Camera::default(),
+            last_motion: Vec3f::new(0.0, 0.0, 0.0),
+            last_turn: Vec2f::new(0.0, 0.0),
+            camera_path: CameraPathPlayer::Idle,
+            movement: Movement::Fly,
+            dragging: None,
         };
     }
 
@@ -54,13 +111,21 @@ impl ApplicationHandler for App {
                 Self::Running {
                     window,
                     graphics,
-                    start_time,
+                    clock,
                     camera,
+                    last_motion,
+                    last_turn,
                     ..
                 },
             ) => {
-                let time: f32 = 0.5 + start_time.elapsed().as_micros() as f32 * 1e-6;
-                graphics.render(camera, time);
+                // Updates lag behind by a fraction of an update, interpolating the camera
+                // between the last two keeps its movement smooth at any frame rate.
+                let mut view = camera.clone();
+                let remaining = 1.0 - clock.alpha();
+                view.position -= *last_motion * remaining;
+                view.yaw -= last_turn.x * remaining;
+                view.pitch -= last_turn.y * remaining;
+                graphics.render(&view, clock.render_time());
                 window.request_redraw();
             }
 
@@ -70,6 +135,40 @@ impl ApplicationHandler for App {
                     graphics, window, ..
                 },
             ) => graphics.resize(window.inner_size()),
+
+            (
+                WindowEvent::MouseInput { state, button, .. },
+                Self::Running {
+                    movement: Movement::Orbit(_),
+                    dragging,
+                    ..
+                },
+            ) => *dragging = (state == ElementState::Pressed).then_some(button),
+            (
+                WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button: MouseButton::Left,
+                    ..
+                },
+                Self::Running {
+                    window,
+                    cursor_grabbed,
+                    ..
+                },
+            ) => *cursor_grabbed = grab_cursor(window, true),
+            (
+                WindowEvent::Focused(false),
+                Self::Running {
+                    window,
+                    cursor_grabbed,
+                    dragging,
+                    ..
+                },
+            ) => {
+                *cursor_grabbed = grab_cursor(window, false);
+                // The button release goes to the focused window, it may never arrive.
+                *dragging = None;
+            }
             _ => (),
         }
     }
@@ -83,63 +182,42 @@ impl ApplicationHandler for App {
         match (event, self) {
             (
                 DeviceEvent::Key(RawKeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::F5),
-                    ..
-                }),
-                Self::Running { graphics, .. },
-            ) => graphics.refresh(),
-            (
-                DeviceEvent::Key(RawKeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::KeyW),
+                    physical_key: PhysicalKey::Code(key),
                     state,
-                    ..
                 }),
-                Self::Running { camera, .. },
-            ) => camera.controller.forward = state != ElementState::Released,
+                app @ Self::Running { .. },
+            ) => app.key_input(key, state),
             (
-                DeviceEvent::Key(RawKeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::KeyS),
-                    state,
-                    ..
-                }),
-                Self::Running { camera, .. },
-            ) => camera.controller.backward = state != ElementState::Released,
-            (
-                DeviceEvent::Key(RawKeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::KeyA),
-                    state,
-                    ..
-                }),
-                Self::Running { camera, .. },
-            ) => camera.controller.left = state != ElementState::Released,
-            (
-                DeviceEvent::Key(RawKeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::KeyD),
-                    state,
+                DeviceEvent::MouseMotion { delta: (x, y) },
+                Self::Running {
+                    camera,
+                    movement: Movement::Orbit(orbit),
+                    dragging: Some(button),
                     ..
-                }),
-                Self::Running { camera, .. },
-            ) => camera.controller.right = state != ElementState::Released,
+                },
+            ) => match button {
+                MouseButton::Left => orbit.rotate(camera, x as f32, y as f32),
+                _ => orbit.pan(camera, x as f32, y as f32),
+            },
             (
-                DeviceEvent::Key(RawKeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::Space),
-                    state,
+                DeviceEvent::MouseMotion { delta: (x, y) },
+                Self::Running {
+                    camera,
+                    cursor_grabbed: true,
                     ..
-                }),
-                Self::Running { camera, .. },
-            ) => camera.controller.up = state != ElementState::Released,
+                },
+            ) => camera.look(x as f32, y as f32),
             (
-                DeviceEvent::Key(RawKeyEvent {
-                    physical_key: PhysicalKey::Code(KeyCode::ShiftLeft),
-                    state,
+                DeviceEvent::MouseWheel { delta },
+                Self::Running {
+                    camera,
+                    movement: Movement::Orbit(orbit),
                     ..
-                }),
-                Self::Running { camera, .. },
-            ) => camera.controller.down = state != ElementState::Released,
-            (DeviceEvent::MouseWheel { delta, .. }, Self::Running { camera, .. }) => match delta {
-                MouseScrollDelta::LineDelta(_, y) => camera.controller.speed += 0.1 * y,
-                MouseScrollDelta::PixelDelta(pos) => camera.controller.speed += 0.1 * pos.y as f32,
-            },
+                },
+            ) => orbit.dolly(camera, scroll_lines(delta)),
+            (DeviceEvent::MouseWheel { delta }, Self::Running { camera, .. }) => {
+                camera.controller.change_speed(scroll_lines(delta))
+            }
             _ => (),
         }
     }
@@ -147,14 +225,194 @@ impl ApplicationHandler for App {
     fn about_to_wait(&mut self, _: &ActiveEventLoop) {
         if let Self::Running {
             last_update,
+            clock,
+            graphics,
             camera,
+            last_motion,
+            last_turn,
+            gamepad,
+            camera_path,
+            movement,
             ..
         } = self
         {
-            let dt = last_update.elapsed().as_secs_f32();
+            let updates = clock.advance(last_update.elapsed().as_secs_f32());
             *last_update = Instant::now();
 
-            camera.update_movement(dt);
+            camera.controller.gamepad = gamepad
+                .as_mut()
+                .and_then(|gamepad| gamepad.poll())
+                .unwrap_or_default();
+            // Probing waits for the GPU, so it happens once for all the updates of the frame and
+            // the probed voxels are reused for a while, see `VoxelProbe::sample_around`.
+            let world = match movement {
+                Movement::Walk(body) if updates > 0 && !camera_path.is_playing() => {
+                    let (origin, size) = body.reach(camera, FIXED_DT * updates as f32);
+                    graphics
+                        .voxels_around(origin, size, clock.time())
+                        .map_err(|e| eprintln!("Could not probe the voxels around the body: {e}"))
+                        .ok()
+                }
+                _ => None,
+            };
+            for _ in 0..updates {
+                let previous_position = camera.position;
+                let previous_rotation = Vec2f::new(camera.yaw, camera.pitch);
+                if !camera_path.is_playing() {
+                    match movement {
+                        Movement::Fly => camera.update_movement(FIXED_DT),
+                        Movement::Walk(body) => {
+                            if let Some(world) = world {
+                                body.update(camera, world, FIXED_DT);
+                            }
+                        }
+                        Movement::Orbit(orbit) => orbit.update(camera, FIXED_DT),
+                    }
+                }
+                camera_path.update(camera, FIXED_DT);
+                clock.tick();
+                *last_motion = camera.position - previous_position;
+                *last_turn = Vec2f::new(camera.yaw, camera.pitch) - previous_rotation;
+            }
         }
     }
 }
+
+/// Grabs or releases the cursor, returning whether it is grabbed.
+fn grab_cursor(window: &Window, grab: bool) -> bool {
+    if !grab {
+        let _ = window.set_cursor_grab(CursorGrabMode::None);
+        window.set_cursor_visible(true);
+        return false;
+    }
+
+    // Not every platform can lock the cursor in place.
+    let grabbed = window
+        .set_cursor_grab(CursorGrabMode::Locked)
+        .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+    match grabbed {
+        Ok(()) => {
+            window.set_cursor_visible(false);
+            true
+        }
+        Err(e) => {
+            eprintln!("Could not grab the cursor: {e}");
+            false
+        }
+    }
+}
+
+impl App {
+    fn key_input(&mut self, key: KeyCode, state: ElementState) {
+        let Self::Running {
+            clock,
+            window,
+            graphics,
+            cursor_grabbed,
+            input,
+            camera,
+            camera_path,
+            movement,
+            dragging,
+            ..
+        } = self
+        else {
+            return;
+        };
+
+        let pressed = state == ElementState::Pressed;
+        for action in input.key(key, pressed) {
+            let held = input.is_held(action);
+            match action {
+                Action::MoveForward => camera.controller.forward = held,
+                Action::MoveBackward => camera.controller.backward = held,
+                Action::MoveLeft => camera.controller.left = held,
+                Action::MoveRight => camera.controller.right = held,
+                Action::MoveUp => camera.controller.up = held,
+                Action::MoveDown => camera.controller.down = held,
+                Action::ZoomIn => camera.controller.zoom_in = held,
+                Action::ZoomOut => camera.controller.zoom_out = held,
+                _ if !pressed => {}
+                Action::NextProjection => camera.projection = camera.projection.next(),
+                Action::ToggleWalk => {
+                    camera.velocity = Vec3f::new(0.0, 0.0, 0.0);
+                    *movement = match movement {
+                        Movement::Walk(_) => Movement::Fly,
+                        _ => Movement::Walk(Body::new(camera)),
+                    }
+                }
+                Action::ToggleOrbit => {
+                    camera.velocity = Vec3f::new(0.0, 0.0, 0.0);
+                    *dragging = None;
+                    *movement = match movement {
+                        Movement::Orbit(_) => Movement::Fly,
+                        _ => {
+                            // The mouse drags the view instead of looking around.
+                            *cursor_grabbed = grab_cursor(window, false);
+                            Movement::Orbit(Orbit::in_front(camera, graphics.center_depth()))
+                        }
+                    }
+                }
+                Action::RecordCameraPath => match camera_path {
+                    CameraPathPlayer::Recording { .. } => {
+                        let path = camera_path.stop(camera).unwrap_or_default();
+                        match path.save(CAMERA_PATH_FILE) {
+                            Ok(()) => println!("Saved camera path to {CAMERA_PATH_FILE}"),
+                            Err(e) => eprintln!("Could not save camera path: {e}"),
+                        }
+                    }
+                    _ => *camera_path = CameraPathPlayer::record(),
+                },
+                Action::PlayCameraPath => match camera_path {
+                    CameraPathPlayer::Playing { .. } => {
+                        camera_path.stop(camera);
+                    }
+                    _ => match CameraPath::load(CAMERA_PATH_FILE) {
+                        Ok(path) => *camera_path = CameraPathPlayer::play(path),
+                        Err(e) => eprintln!("Could not load camera path: {e}"),
+                    },
+                },
+                Action::Refresh => graphics.refresh(),
+                Action::NextDebugView => graphics.debug_view = graphics.debug_view.next(),
+                Action::PreviousDebugView => graphics.debug_view = graphics.debug_view.previous(),
+                Action::Screenshot | Action::HighResScreenshot => {
+                    let options = ScreenshotOptions {
+                        scale: if action == Action::HighResScreenshot {
+                            HIGH_RES_SCREENSHOT_SCALE
+                        } else {
+                            1
+                        },
+                        ..Default::default()
+                    };
+                    match graphics.screenshot(camera, clock.render_time(), options, SCREENSHOT_DIR)
+                    {
+                        Ok(path) => println!("Saved screenshot to {}", path.display()),
+                        Err(e) => eprintln!("Could not save screenshot: {e}"),
+                    }
+                }
+                Action::PauseTime => {
+                    clock.toggle_pause();
+                    println!("Time {}", if clock.paused { "paused" } else { "resumed" });
+                }
+                Action::StepTime => clock.step(),
+                Action::SlowDownTime | Action::SpeedUpTime => {
+                    clock.change_time_scale(if action == Action::SpeedUpTime {
+                        1.0
+                    } else {
+                        -1.0
+                    });
+                    println!("Time scale {}", clock.time_scale);
+                }
+                Action::ReleaseCursor => *cursor_grabbed = grab_cursor(window, false),
+            }
+        }
+    }
+}
+
+/// Mouse wheel notches scrolled, touchpad scrolling is converted to an equivalent.
+fn scroll_lines(delta: MouseScrollDelta) -> f32 {
+    match delta {
+        MouseScrollDelta::LineDelta(_, y) => y,
+        MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / PIXELS_PER_SCROLL_LINE,
+    }
+}
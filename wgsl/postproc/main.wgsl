@@ -20,9 +20,59 @@ fn vs_main(
 var r_color: texture_2d<f32>;
 @group(0) @binding(1)
 var r_sampler: sampler;
+@group(0) @binding(2)
+var r_depth: texture_2d<f32>;
+
+const FOG_COLOR = vec3f(0.75, 0.85, 1.0);
+const FOG_START = 2.0;
+const FOG_END = 10.0;
+
+const EDGE_THRESHOLD = 0.1;
+const EDGE_COLOR = vec3f(0.0);
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let color = textureSample(r_color, r_sampler, in.tex_coord);
-    return vec4f(pow(color.xyz, vec3f(2.2)), 1); // color correction
+    let coords = vec2i(in.position.xy);
+    let depth = depth_at(coords);
+
+    var color = textureSample(r_color, r_sampler, in.tex_coord).xyz;
+    if depth >= 0.0 {
+        color = mix(color, FOG_COLOR, fog(depth));
+    }
+    color = mix(color, EDGE_COLOR, edge(coords, depth));
+
+    return vec4f(pow(color, vec3f(2.2)), 1); // color correction
+}
+
+fn depth_at(coords: vec2i) -> f32 {
+    let size = vec2i(textureDimensions(r_depth));
+    return textureLoad(r_depth, clamp(coords, vec2i(0), size - 1), 0).x;
+}
+
+fn fog(depth: f32) -> f32 {
+    return clamp((depth - FOG_START) / (FOG_END - FOG_START), 0.0, 1.0);
+}
+
+// Outlines pixels whose depth differs too much from one of their neighbours.
+// Negative depths are rays that hit nothing.
+fn edge(coords: vec2i, depth: f32) -> f32 {
+    if depth < 0.0 {
+        return 0.0;
+    }
+
+    var neighbours = array(
+        depth_at(coords + vec2i(1, 0)),
+        depth_at(coords - vec2i(1, 0)),
+        depth_at(coords + vec2i(0, 1)),
+        depth_at(coords - vec2i(0, 1)),
+    );
+
+    var outline = 0.0;
+    for (var i = 0; i < 4; i++) {
+        let other = neighbours[i];
+        if other < 0.0 || abs(depth - other) > EDGE_THRESHOLD * min(depth, other) {
+            outline = 1.0;
+        }
+    }
+    return outline;
 }
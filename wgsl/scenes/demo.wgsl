@@ -0,0 +1,58 @@
+// Animated sphere and torus, the default scene.
+//
+// A scene provides `scene_material`, returning the material of the voxel at a position or
+// `MATERIAL_NONE` when it is empty, and `scene_material_color` used by the materials debug view.
+
+#import voxel/maths
+
+const MATERIAL_SPHERE = 1u;
+const MATERIAL_TORUS = 2u;
+
+fn scene_material(voxel_pos: vec3f, time: f32) -> u32 {
+    if intersect_sphere(voxel_pos, time) {
+        return MATERIAL_SPHERE;
+    }
+    if intersect_torus(voxel_pos, time) {
+        return MATERIAL_TORUS;
+    }
+    return MATERIAL_NONE;
+}
+
+fn scene_material_color(material: u32) -> vec3f {
+    switch material {
+        case MATERIAL_SPHERE: {
+            return vec3f(0.9, 0.3, 0.2);
+        }
+        case MATERIAL_TORUS: {
+            return vec3f(0.2, 0.5, 0.9);
+        }
+        default: {
+            return vec3f(1.0, 0.0, 1.0);
+        }
+    }
+}
+
+fn intersect_torus(voxel_pos: vec3f, time: f32) -> bool {
+    let a = fract(time * 0.3) * 2.0 * PI;
+    let rot = rot_mat_pitch(a) * rot_mat_roll(a * 0.5);
+
+    let o = vec3f(-1.5, 0.0, -1.0) * rot;
+
+    let major_r = 0.8;
+    let minor_r = 0.2;
+    
+    let p0 = (dot(rot.x, voxel_pos) - o.x);
+    let p1 = (dot(rot.y, voxel_pos) - o.y);
+    let p2 = (dot(rot.z, voxel_pos) - o.z);
+    
+    let p3 = sqrt(p0 * p0 + p1 * p1) - major_r;
+
+    return p3 * p3 + p2 * p2 <= minor_r;
+}
+
+fn intersect_sphere(voxel_pos: vec3f, time: f32) -> bool {
+    let o = vec3f(1.5, -.5, -1.0);
+    let r = (sin(time * 1.5) + 1.3) / 2.;
+
+    return length(voxel_pos - o) < r;
+}
@@ -1,12 +1,23 @@
+#import maths
+
 struct Camera {
     position: vec3f,
+    // Distance to the plane in focus, for depth of field.
+    focal_length: f32,
     upper_left: vec3f,
+    // Rays are parallel to `direction` and start on the viewport when set.
+    orthographic: u32,
     pixel_delta_u: vec3f,
     pixel_delta_v: vec3f,
+    direction: vec3f,
 }
 
 fn camera_ray(camera: Camera, coords: vec2u) -> Ray {
     let pixel_center = camera.upper_left + (f32(coords.x) * camera.pixel_delta_u) + (f32(coords.y) * camera.pixel_delta_v);
+    if camera.orthographic != 0u {
+        return Ray(pixel_center, camera.direction);
+    }
+
     let ray_dir = pixel_center - camera.position;
     return Ray(camera.position, ray_dir);
 }
\ No newline at end of file
@@ -1,3 +1,9 @@
+// `VOXEL_SIZE` comes from the rust side, see `Scene::defines`.
+
+#ifndef VIEW_DISTANCE
+#define VIEW_DISTANCE 10.
+#endif
+
 #import maths
 #import camera
 #import skybox
@@ -7,36 +13,48 @@ struct Params {
     width: u32,
     height: u32,
     time: f32,
+    debug_view: u32,
 };
 
 @group(0) @binding(0) var outputTex: texture_storage_2d<rgba8unorm, write>;
 @group(0) @binding(1) var<uniform> params: Params;
 @group(0) @binding(2) var<uniform> camera: Camera;
+@group(0) @binding(3) var depthTex: texture_storage_2d<r32float, write>;
 
 @compute @workgroup_size(16, 16)
 fn main(@builtin(global_invocation_id) global_ix: vec3u) {
-    textureStore(
-        outputTex,
-        vec2i(global_ix.xy),
-        color_at(global_ix.xy, params, camera)
-    );
+    // The last workgroups overlap the edges when the size isn't a multiple of theirs.
+    if global_ix.x >= params.width || global_ix.y >= params.height {
+        return;
+    }
+
+    let pixel = color_at(global_ix.xy, params, camera);
+    textureStore(outputTex, vec2i(global_ix.xy), pixel.color);
+    textureStore(depthTex, vec2i(global_ix.xy), vec4f(pixel.depth, 0.0, 0.0, 0.0));
 }
 
-const VOXEL_SIZE = 0.1;
-const VIEW_DISTANCE = 10.;
+struct Pixel {
+    color: vec4f,
+    depth: f32,
+}
 
-fn color_at(coords: vec2u, params: Params, camera: Camera) -> vec4f {
+fn color_at(coords: vec2u, params: Params, camera: Camera) -> Pixel {
     let ray = camera_ray(camera, coords);
 
     var rgb = skybox(ray.dir);
 
     let voxel_record = voxel_traversal(ray, VOXEL_SIZE, params.time);
+#ifndef NO_DEBUG_VIEWS
+    if params.debug_view != DEBUG_VIEW_SHADED {
+        return Pixel(vec4f(debug_color(params.debug_view, ray, voxel_record), 1.0), voxel_record.distance);
+    }
+#endif
     if voxel_record.intersect {
         let voxel_color = (voxel_record.pos + vec3f(1.)) * 0.5;
         rgb = voxel_color * lighting(voxel_record.normal, ray.dir);
     }
     
-    return vec4f(rgb, 1.0);
+    return Pixel(vec4f(rgb, 1.0), voxel_record.distance);
 }
 
 fn lighting(normal: vec3f, dir: vec3f) -> vec3f {
@@ -52,3 +70,64 @@ fn lighting(normal: vec3f, dir: vec3f) -> vec3f {
     return diffuse_attn * light * 1.0 + specular_attn * light * 0.6 + ambient;
 }
 
+
+// Must match `DebugView` on the rust side.
+const DEBUG_VIEW_SHADED = 0u;
+const DEBUG_VIEW_NORMALS = 1u;
+const DEBUG_VIEW_DEPTH = 2u;
+const DEBUG_VIEW_STEPS = 3u;
+const DEBUG_VIEW_MATERIALS = 4u;
+const DEBUG_VIEW_BOUNDARIES = 5u;
+
+// Number of voxels along a chunk edge, only used to draw chunk boundaries.
+const CHUNK_SIZE = 8.0;
+
+fn debug_color(view: u32, ray: Ray, record: VoxelRecord) -> vec3f {
+    if view == DEBUG_VIEW_STEPS {
+        return heatmap(f32(record.steps) / (VIEW_DISTANCE / VOXEL_SIZE));
+    }
+    if !record.intersect {
+        return vec3f(0.0);
+    }
+
+    switch view {
+        case DEBUG_VIEW_NORMALS: {
+            return record.normal * 0.5 + vec3f(0.5);
+        }
+        case DEBUG_VIEW_DEPTH: {
+            return vec3f(1.0 - record.distance / VIEW_DISTANCE);
+        }
+        case DEBUG_VIEW_MATERIALS: {
+            return scene_material_color(record.material);
+        }
+        case DEBUG_VIEW_BOUNDARIES: {
+            let hit = ray.origin + normalize(ray.dir) * record.distance;
+            let voxel_edge = boundary_distance(hit / VOXEL_SIZE, record.normal);
+            let chunk_edge = boundary_distance(hit / (VOXEL_SIZE * CHUNK_SIZE), record.normal);
+
+            var rgb = vec3f(0.5) * lighting(record.normal, ray.dir);
+            if voxel_edge < 0.05 {
+                rgb = vec3f(0.1);
+            }
+            if chunk_edge < 0.05 / CHUNK_SIZE {
+                rgb = vec3f(1.0, 0.2, 0.2);
+            }
+            return rgb;
+        }
+        default: {
+            return vec3f(1.0, 0.0, 1.0);
+        }
+    }
+}
+
+// Distance from a point on a grid cell face to the closest edge of that face, in cell units.
+fn boundary_distance(cell_pos: vec3f, normal: vec3f) -> f32 {
+    let f = fract(cell_pos);
+    let d = min(f, vec3f(1.0) - f) + abs(normal); // Ignore the axis the face is aligned on
+    return min(d.x, min(d.y, d.z));
+}
+
+fn heatmap(t: f32) -> vec3f {
+    let x = clamp(t, 0.0, 1.0);
+    return clamp(vec3f(x * 3.0 - 1.0, 1.0 - abs(x * 2.0 - 1.0) * 2.0 + 0.5, 1.0 - x * 3.0), vec3f(0.0), vec3f(1.0));
+}
@@ -0,0 +1,26 @@
+// Materials of a box of voxels, read back to collide with the scene on the CPU.
+
+// Only needed by the traversal, which isn't used here.
+#define VIEW_DISTANCE 0.
+
+#import traversal
+
+struct ProbeParams {
+    origin: vec3i,
+    time: f32,
+    size: vec3u,
+}
+
+@group(0) @binding(0) var<uniform> params: ProbeParams;
+@group(0) @binding(1) var<storage, read_write> materials: array<u32>;
+
+@compute @workgroup_size(4, 4, 4)
+fn main(@builtin(global_invocation_id) global_ix: vec3u) {
+    if any(global_ix >= params.size) {
+        return;
+    }
+
+    let voxel = params.origin + vec3i(global_ix);
+    let index = global_ix.x + params.size.x * (global_ix.y + params.size.y * global_ix.z);
+    materials[index] = scene_material(vec3f(voxel) * VOXEL_SIZE, params.time);
+}